@@ -40,7 +40,7 @@ fn fragment(input: VertexOutput) -> [[location(0)]]vec4<f32> {
     var background = vec4<f32>(0.8, 0.8, 0.8, 1.0);
     var output_color = vec4<f32>(0.0,0.0,0.0,1.0);
 
-    if (input.uv.x > (uniform_data.percentage + 1.0) / 2.0)  {
+    if (input.uv.x > uniform_data.percentage)  {
         output_color = uniform_data.color_1;
     } else {
         output_color = uniform_data.color_2;
@@ -49,11 +49,7 @@ fn fragment(input: VertexOutput) -> [[location(0)]]vec4<f32> {
     var gray = vec4<f32>(0.2, 0.2, 0.2, 1.0);
     var gray_mix_factor: f32;
 
-    if (uniform_data.percentage > 0.0) {
-       gray_mix_factor = smoothStep(0.0, 1.0, uniform_data.percentage * 3.5);
-    } else {
-       gray_mix_factor = smoothStep(0.0, 1.0, -uniform_data.percentage * 3.5);
-    }
+    gray_mix_factor = smoothStep(0.0, 1.0, uniform_data.percentage * 3.5);
 
     output_color = mix(gray, output_color, clamp(gray_mix_factor, 0.3, 1.0));
 
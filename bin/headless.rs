@@ -0,0 +1,93 @@
+use std::{fs, path::Path};
+
+use bevy::utils::Duration;
+use rust_gamejam::{
+    bot::take_over_player,
+    headless::{build_headless_app, run_match, MAX_STEPS},
+    map::Map,
+    prelude::Factions,
+    replay::{Replay, ReplayOutcome, ReplayPlugin},
+};
+
+/// Usage:
+/// - `headless [map]` plays a bot match
+/// - `headless --record <replay> [map]` plays a bot match and records it
+/// - `headless --replay <replay>` re-simulates a recording and checks it ends the same way
+fn main() -> anyhow::Result<()> {
+    let mut args = std::env::args().skip(1);
+    let mut map_path = "assets/main.map".to_string();
+    let mut record_path = None;
+    let mut replay = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--record" => record_path = args.next(),
+            "--replay" => replay = Some(Replay::load(&args.next().unwrap_or_default())?),
+            _ => map_path = arg,
+        }
+    }
+    if let Some(replay) = &replay {
+        map_path = replay.map.clone();
+    }
+
+    let name = Path::new(&map_path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let map = Map::parse(&fs::read(&map_path)?, &name)?;
+    let timestep = replay
+        .as_ref()
+        .map(|replay| replay.timestep)
+        .unwrap_or_else(|| Duration::from_secs_f32(1.0 / 60.0));
+
+    let mut app = build_headless_app(map, timestep)?;
+    match &replay {
+        Some(replay) => {
+            app.add_plugin(ReplayPlugin::Playback(replay.clone()));
+        }
+        None => {
+            app.add_system(take_over_player);
+            if let Some(path) = record_path {
+                app.add_plugin(ReplayPlugin::Record {
+                    path,
+                    map: map_path.clone(),
+                    timestep: Some(timestep),
+                });
+            }
+        }
+    }
+
+    let result = match run_match(&mut app, MAX_STEPS) {
+        Some(result) => result,
+        None => anyhow::bail!(
+            "match on {} did not finish in {} steps",
+            map_path,
+            MAX_STEPS
+        ),
+    };
+    let winner = match result.winner {
+        Some(team) => match app.world.resource::<Factions>().get(team) {
+            Some(faction) => format!("{} won", faction.name),
+            None => format!("Team {} won", team.0),
+        },
+        None => "Draw".to_string(),
+    };
+    println!(
+        "{} by {:?} after {:.1}s",
+        winner,
+        result.reason,
+        result.duration.as_secs_f32()
+    );
+
+    if let Some(expected) = replay.and_then(|replay| replay.outcome) {
+        let outcome = ReplayOutcome::from_world(&mut app.world, result.won());
+        if outcome != expected {
+            anyhow::bail!(
+                "replay desynced, expected {:?} but got {:?}",
+                expected,
+                outcome
+            );
+        }
+        println!("Replay matched");
+    }
+    Ok(())
+}
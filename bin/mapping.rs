@@ -1,12 +1,16 @@
 #![allow(clippy::type_complexity)]
 
-use std::fs;
+use std::{fs, path::Path};
 
+use anyhow::Context;
 use bevy::{
-    input::mouse::MouseWheel, prelude::*, render::camera::ScalingMode,
+    ecs::system::SystemParam, input::mouse::MouseWheel, prelude::*, render::camera::ScalingMode,
     sprite::MaterialMesh2dBundle, window::PresentMode,
 };
-use bevy_inspector_egui::{WorldInspectorParams, WorldInspectorPlugin};
+use bevy_inspector_egui::{
+    bevy_egui::{egui, EguiContext},
+    WorldInspectorParams, WorldInspectorPlugin,
+};
 use ron::ser::{to_string_pretty, PrettyConfig};
 
 use bevy_mod_picking::*;
@@ -16,10 +20,31 @@ pub const HEIGHT: f32 = 900.0;
 pub const RESOLUTION: f32 = 16.0 / 9.0;
 
 use rust_gamejam::{
-    map::{Map, Rect},
-    prelude::ChickenOrDog,
+    map::{validate, Map, MapBounds, MapIssue, Rect, MAP_VERSION},
+    prelude::Team,
+    rules::MatchRules,
 };
 
+/// Editor colors of the teams a spawner can be placed for
+const TEAM_COLORS: [Color; 4] = [Color::YELLOW, Color::CRIMSON, Color::BLUE, Color::PURPLE];
+
+fn team_color(team: Team) -> Color {
+    TEAM_COLORS
+        .get(team.0 as usize)
+        .copied()
+        .unwrap_or(Color::GRAY)
+}
+
+/// The map file being edited
+struct MapFile(String);
+
+/// Name, author and rules of the map being edited, kept as they were loaded
+struct MapInfo {
+    name: String,
+    author: String,
+    rules: MatchRules,
+}
+
 #[derive(Component)]
 struct WallSquare;
 #[derive(Component)]
@@ -28,17 +53,26 @@ struct PlayerSpawn;
 struct EnemySpawn;
 #[derive(Component)]
 struct Spawner;
+/// Hero spawn of a team after the first two
+#[derive(Component)]
+struct HeroSpawn;
 
-fn save_map(
-    map: Query<&Transform, With<WallSquare>>,
-    spawners_query: Query<(&Transform, &ChickenOrDog), With<Spawner>>,
-    player_spawn: Query<&Transform, With<PlayerSpawn>>,
-    enemy_spawn: Query<&Transform, With<EnemySpawn>>,
-    input: Res<Input<KeyCode>>,
-) {
-    if input.just_pressed(KeyCode::Return) {
+/// The map as it stands in the editor
+#[derive(SystemParam)]
+struct EditedMap<'w, 's> {
+    walls: Query<'w, 's, &'static Transform, With<WallSquare>>,
+    spawners: Query<'w, 's, (&'static Transform, &'static Team), With<Spawner>>,
+    hero_spawns: Query<'w, 's, (&'static Transform, &'static Team), With<HeroSpawn>>,
+    player_spawn: Query<'w, 's, &'static Transform, With<PlayerSpawn>>,
+    enemy_spawn: Query<'w, 's, &'static Transform, With<EnemySpawn>>,
+    info: Res<'w, MapInfo>,
+}
+
+impl<'w, 's> EditedMap<'w, 's> {
+    /// Always in the latest format
+    fn to_map(&self) -> Map {
         let mut rects = Vec::new();
-        for transform in map.iter() {
+        for transform in self.walls.iter() {
             rects.push(Rect {
                 position: transform.translation.truncate(),
                 size: transform.scale.truncate(),
@@ -47,27 +81,52 @@ fn save_map(
         }
 
         let mut spawn_locations = Vec::new();
-        for (transform, chicken) in spawners_query.iter() {
-            spawn_locations.push((transform.translation.truncate(), *chicken));
+        for (transform, team) in self.spawners.iter() {
+            spawn_locations.push((transform.translation.truncate(), *team));
         }
-        let player_spawn = player_spawn.single().translation.truncate();
-        let enemy_spawn = enemy_spawn.single().translation.truncate();
+        let hero_spawns = self
+            .hero_spawns
+            .iter()
+            .map(|(transform, team)| (transform.translation.truncate(), *team))
+            .collect();
+        let player_spawn = self.player_spawn.single().translation.truncate();
+        let enemy_spawn = self.enemy_spawn.single().translation.truncate();
 
-        let data = Map {
+        Map {
+            version: MAP_VERSION,
+            name: self.info.name.clone(),
+            author: self.info.author.clone(),
+            bounds: MapBounds::around(&rects),
             rects,
             spawn_locations,
             player_spawn,
             enemy_spawn,
-        };
+            hero_spawns,
+            rules: self.info.rules.clone(),
+        }
+    }
+}
+
+fn save_map(edited: EditedMap, input: Res<Input<KeyCode>>, map_file: Res<MapFile>) {
+    if input.just_pressed(KeyCode::Return) {
+        let data = edited.to_map();
 
         let pretty = PrettyConfig::new()
             .depth_limit(2)
             .separate_tuple_members(true)
             .enumerate_arrays(true);
-        let s = to_string_pretty(&data, pretty).expect("Serialization failed");
+        let s = match to_string_pretty(&data, pretty) {
+            Ok(s) => s,
+            Err(e) => {
+                error!("Failed to serialize map: {}", e);
+                return;
+            }
+        };
 
-        fs::write("assets/main.map", s).expect("Unable to write file");
-        println!("SAVED");
+        match fs::write(&map_file.0, s) {
+            Ok(()) => println!("SAVED {}", map_file.0),
+            Err(e) => error!("Failed to write {}: {}", map_file.0, e),
+        }
     }
 }
 
@@ -75,84 +134,135 @@ fn load_map(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<ColorMaterial>>,
+    map_file: Res<MapFile>,
 ) {
-    if let Ok(map) =
-        ron::de::from_str::<Map>(&fs::read_to_string("assets/main.map").unwrap_or_default())
-    {
-        for rect in &map.rects {
-            commands
-                .spawn_bundle(MaterialMesh2dBundle {
-                    mesh: meshes.add(Mesh::from(shape::Quad::default())).into(),
-                    material: materials.add(ColorMaterial::from(Color::WHITE)),
-                    transform: Transform {
-                        translation: rect.position.extend(1.0),
-                        //rotation: Quat::from_axis_angle(Vec3::Z, rect.rotation),
-                        rotation: Quat::from_euler(EulerRot::XYZ, 0.0, 0.0, rect.rotation),
-                        scale: rect.size.extend(1.0),
-                    },
-                    ..default()
-                })
-                .insert(WallSquare)
-                .insert(Name::new("Wall"))
-                .insert_bundle(PickableBundle::default());
-        }
-        for spawners in &map.spawn_locations {
-            let color = match spawners.1 {
-                ChickenOrDog::Chicken => Color::YELLOW,
-                ChickenOrDog::Dog => Color::CRIMSON,
-            };
-            commands
-                .spawn_bundle(MaterialMesh2dBundle {
-                    mesh: meshes.add(Mesh::from(shape::Quad::default())).into(),
-                    material: materials.add(ColorMaterial::from(color)),
-                    transform: Transform {
-                        translation: spawners.0.extend(2.2),
-                        //rotation: Quat::from_axis_angle(Vec3::Z, rect.rotation),
-                        scale: Vec3::splat(0.1),
-                        ..Default::default()
-                    },
-                    ..default()
-                })
-                .insert(Spawner)
-                .insert(spawners.1)
-                .insert(Name::new("Wall"))
-                .insert_bundle(PickableBundle::default());
+    let name = Path::new(&map_file.0)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let map = fs::read(&map_file.0)
+        .with_context(|| format!("failed to read {}", map_file.0))
+        .and_then(|contents| {
+            Map::parse(&contents, &name).with_context(|| format!("failed to parse {}", map_file.0))
+        });
+    let map = match map {
+        Ok(map) => map,
+        Err(e) => {
+            warn!("{:?}, starting a new map", e);
+            let mut map = Map::new(&name);
+            map.author = std::env::var("USER").unwrap_or_default();
+            map
         }
+    };
+    commands.insert_resource(MapInfo {
+        name: map.name.clone(),
+        author: map.author.clone(),
+        rules: map.rules.clone(),
+    });
+
+    for rect in &map.rects {
         commands
             .spawn_bundle(MaterialMesh2dBundle {
                 mesh: meshes.add(Mesh::from(shape::Quad::default())).into(),
-                material: materials.add(ColorMaterial::from(Color::GREEN)),
+                material: materials.add(ColorMaterial::from(Color::WHITE)),
                 transform: Transform {
-                    translation: map.player_spawn.extend(0.1),
-                    scale: Vec3::splat(0.1),
+                    translation: rect.position.extend(1.0),
                     //rotation: Quat::from_axis_angle(Vec3::Z, rect.rotation),
-                    ..Default::default()
+                    rotation: Quat::from_euler(EulerRot::XYZ, 0.0, 0.0, rect.rotation),
+                    scale: rect.size.extend(1.0),
                 },
                 ..default()
             })
-            .insert(PlayerSpawn)
-            .insert(Name::new("Player"))
+            .insert(WallSquare)
+            .insert(Name::new("Wall"))
             .insert_bundle(PickableBundle::default());
+    }
+    for spawners in &map.spawn_locations {
+        let color = team_color(spawners.1);
         commands
             .spawn_bundle(MaterialMesh2dBundle {
                 mesh: meshes.add(Mesh::from(shape::Quad::default())).into(),
-                material: materials.add(ColorMaterial::from(Color::AQUAMARINE)),
+                material: materials.add(ColorMaterial::from(color)),
                 transform: Transform {
-                    translation: map.enemy_spawn.extend(0.1),
-                    scale: Vec3::splat(0.1),
+                    translation: spawners.0.extend(2.2),
                     //rotation: Quat::from_axis_angle(Vec3::Z, rect.rotation),
+                    scale: Vec3::splat(0.1),
                     ..Default::default()
                 },
                 ..default()
             })
-            .insert(EnemySpawn)
-            .insert(Name::new("Enemy"))
+            .insert(Spawner)
+            .insert(spawners.1)
+            .insert(Name::new("Wall"))
             .insert_bundle(PickableBundle::default());
     }
+    for (location, team) in &map.hero_spawns {
+        spawn_hero_spawn(&mut commands, &mut meshes, &mut materials, *location, *team);
+    }
+    commands
+        .spawn_bundle(MaterialMesh2dBundle {
+            mesh: meshes.add(Mesh::from(shape::Quad::default())).into(),
+            material: materials.add(ColorMaterial::from(Color::GREEN)),
+            transform: Transform {
+                translation: map.player_spawn.extend(0.1),
+                scale: Vec3::splat(0.1),
+                //rotation: Quat::from_axis_angle(Vec3::Z, rect.rotation),
+                ..Default::default()
+            },
+            ..default()
+        })
+        .insert(PlayerSpawn)
+        .insert(Name::new("Player"))
+        .insert_bundle(PickableBundle::default());
+    commands
+        .spawn_bundle(MaterialMesh2dBundle {
+            mesh: meshes.add(Mesh::from(shape::Quad::default())).into(),
+            material: materials.add(ColorMaterial::from(Color::AQUAMARINE)),
+            transform: Transform {
+                translation: map.enemy_spawn.extend(0.1),
+                scale: Vec3::splat(0.1),
+                //rotation: Quat::from_axis_angle(Vec3::Z, rect.rotation),
+                ..Default::default()
+            },
+            ..default()
+        })
+        .insert(EnemySpawn)
+        .insert(Name::new("Enemy"))
+        .insert_bundle(PickableBundle::default());
 }
 
+fn spawn_hero_spawn(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<ColorMaterial>,
+    location: Vec2,
+    team: Team,
+) {
+    commands
+        .spawn_bundle(MaterialMesh2dBundle {
+            mesh: meshes.add(Mesh::from(shape::Circle::new(0.5))).into(),
+            material: materials.add(ColorMaterial::from(team_color(team))),
+            transform: Transform {
+                translation: location.extend(0.1),
+                scale: Vec3::splat(0.1),
+                ..Default::default()
+            },
+            ..default()
+        })
+        .insert(HeroSpawn)
+        .insert(team)
+        .insert(Name::new("Hero Spawn"))
+        .insert_bundle(PickableBundle::default());
+}
+
+/// Usage: `mapping [map]`, editing `assets/main.map` by default
 fn main() {
+    let map_file = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "assets/main.map".to_string());
+
     App::new()
+        .insert_resource(MapFile(map_file))
         .insert_resource(ClearColor(CLEAR))
         .insert_resource(WindowDescriptor {
             width: HEIGHT * RESOLUTION,
@@ -173,6 +283,7 @@ fn main() {
         .add_startup_system(load_map)
         .add_system(toggle_inspector)
         .add_system(save_map)
+        .add_system(show_map_issues)
         .add_system(fly_camera)
         .add_plugins(DefaultPickingPlugins)
         .add_plugin(DebugEventsPickingPlugin)
@@ -191,14 +302,22 @@ fn create_square(
         commands
             .spawn_bundle(MaterialMesh2dBundle {
                 mesh: meshes.add(Mesh::from(shape::Quad::default())).into(),
-                material: materials.add(ColorMaterial::from(Color::PURPLE)),
+                material: materials.add(ColorMaterial::from(team_color(Team(0)))),
                 transform: Transform::from_scale(Vec3::splat(0.1)),
                 ..default()
             })
             .insert(Spawner)
             .insert(Name::new("Spawner"))
-            .insert(ChickenOrDog::Chicken)
+            .insert(Team(0))
             .insert_bundle(PickableBundle::default());
+    } else if input.just_pressed(KeyCode::Space) && input.pressed(KeyCode::LShift) {
+        spawn_hero_spawn(
+            &mut commands,
+            &mut meshes,
+            &mut materials,
+            Vec2::ZERO,
+            Team(2),
+        );
     } else if input.just_pressed(KeyCode::Space) {
         commands
             .spawn_bundle(MaterialMesh2dBundle {
@@ -216,13 +335,13 @@ fn move_selected(
     mut transform: Query<(
         &mut Transform,
         &Selection,
-        Option<&mut ChickenOrDog>,
+        Option<&mut Team>,
         &mut Handle<ColorMaterial>,
     )>,
     input: Res<Input<KeyCode>>,
     mut materials: ResMut<Assets<ColorMaterial>>,
 ) {
-    for (mut trans, selected, chicken, mut mat) in transform.iter_mut() {
+    for (mut trans, selected, team, mut mat) in transform.iter_mut() {
         if selected.selected() {
             if input.pressed(KeyCode::I) {
                 trans.translation.y += 0.02;
@@ -254,25 +373,16 @@ fn move_selected(
             if input.pressed(KeyCode::O) {
                 trans.rotation *= Quat::from_axis_angle(Vec3::Z, -0.03);
             }
-            if input.pressed(KeyCode::P) {
-                if let Some(mut chicken) = chicken {
-                    *chicken = ChickenOrDog::Dog;
-                    let color = match *chicken {
-                        ChickenOrDog::Chicken => Color::YELLOW,
-                        ChickenOrDog::Dog => Color::CRIMSON,
-                    };
-                    let color = materials.add(ColorMaterial::from(color));
-                    *mat = color;
+            //P and ; cycle through the teams
+            if let Some(mut team) = team {
+                let teams = TEAM_COLORS.len() as u8;
+                if input.just_pressed(KeyCode::P) {
+                    team.0 = (team.0 + 1) % teams;
+                } else if input.just_pressed(KeyCode::Semicolon) {
+                    team.0 = (team.0 + teams - 1) % teams;
                 }
-            } else if input.pressed(KeyCode::Semicolon) {
-                if let Some(mut chicken) = chicken {
-                    *chicken = ChickenOrDog::Chicken;
-                    let color = match *chicken {
-                        ChickenOrDog::Chicken => Color::YELLOW,
-                        ChickenOrDog::Dog => Color::CRIMSON,
-                    };
-                    let color = materials.add(ColorMaterial::from(color));
-                    *mat = color;
+                if team.is_changed() {
+                    *mat = materials.add(ColorMaterial::from(team_color(*team)));
                 }
             }
         }
@@ -320,6 +430,34 @@ fn spawn_camera(mut commands: Commands) {
         .insert_bundle(PickingCameraBundle::default());
 }
 
+/// Lists everything that would stop the map from being played, rechecked whenever it's edited
+fn show_map_issues(
+    edited: EditedMap,
+    changed: Query<(), (Or<(Changed<Transform>, Changed<Team>)>, Without<Camera>)>,
+    despawned: RemovedComponents<Transform>,
+    mut issues: Local<Option<Vec<MapIssue>>>,
+    mut egui_context: ResMut<EguiContext>,
+) {
+    if issues.is_none() || !changed.is_empty() || despawned.iter().next().is_some() {
+        *issues = Some(validate(&edited.to_map()));
+    }
+    let issues = issues.as_ref().unwrap();
+
+    egui::Window::new("Map issues").show(egui_context.ctx_mut(), |ui| {
+        if issues.is_empty() {
+            ui.label("No issues");
+        }
+        for issue in issues {
+            let color = if issue.is_fatal() {
+                egui::Color32::RED
+            } else {
+                egui::Color32::YELLOW
+            };
+            ui.colored_label(color, issue.to_string());
+        }
+    });
+}
+
 fn toggle_inspector(
     input: ResMut<Input<KeyCode>>,
     mut window_params: ResMut<WorldInspectorParams>,
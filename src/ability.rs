@@ -0,0 +1,470 @@
+use std::collections::BTreeMap;
+
+use heron::rapier_plugin::PhysicsWorld;
+use serde::Deserialize;
+
+use crate::{
+    ai::{EnemyBrain, EnemyGoal, EnemyThinkLabel},
+    damage::{ApplyDamageLabel, DamageRequest},
+    events::AbilityUsed,
+    player::PlayerInputLabel,
+    prelude::*,
+    unit::UnitDef,
+};
+
+/// What an ability does when it's used
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub enum AbilityEffect {
+    /// Rushes `distance` along the hero's aim at `speed`, stopping short of walls
+    Dash { distance: f32, speed: f32 },
+    /// Stuns every enemy unit within `radius` for `duration` seconds and hurts them for `damage`
+    Stun {
+        radius: f32,
+        duration: f32,
+        #[serde(default)]
+        damage: f32,
+    },
+    /// Sends `count` minions out of the owned spawner closest to the hero
+    Summon { count: u32 },
+}
+
+/// A hero ability, part of `GameTuning` and named by the `abilities` of a unit
+#[derive(Debug, Clone, Deserialize)]
+pub struct AbilityDef {
+    /// Seconds before it can be used again
+    pub cooldown: f32,
+    pub effect: AbilityEffect,
+}
+
+/// Every ability by name
+#[derive(Debug, Clone, Deserialize)]
+#[serde(transparent)]
+pub struct Abilities(pub BTreeMap<String, AbilityDef>);
+
+impl Default for Abilities {
+    fn default() -> Self {
+        let dash = AbilityDef {
+            cooldown: 4.0,
+            effect: AbilityEffect::Dash {
+                distance: 0.8,
+                speed: 4.0,
+            },
+        };
+        let stun = AbilityDef {
+            cooldown: 10.0,
+            effect: AbilityEffect::Stun {
+                radius: 0.6,
+                duration: 1.5,
+                damage: 0.5,
+            },
+        };
+        let summon = AbilityDef {
+            cooldown: 20.0,
+            effect: AbilityEffect::Summon { count: 3 },
+        };
+        Self(
+            [("dash", dash), ("stun", stun), ("summon", summon)]
+                .into_iter()
+                .map(|(name, ability)| (name.to_string(), ability))
+                .collect(),
+        )
+    }
+}
+
+impl Abilities {
+    pub fn get(&self, name: &str) -> Option<&AbilityDef> {
+        self.0.get(name)
+    }
+}
+
+/// One of a hero's abilities and how long until it's ready again
+#[derive(Debug, Clone)]
+pub struct AbilitySlot {
+    pub name: String,
+    pub def: AbilityDef,
+    pub cooldown: Timer,
+}
+
+impl AbilitySlot {
+    pub fn ready(&self) -> bool {
+        self.cooldown.finished()
+    }
+}
+
+/// The abilities a hero can use, in the order they're bound to keys and buttons
+#[derive(Component, Debug, Clone, Default)]
+pub struct HeroAbilities(pub Vec<AbilitySlot>);
+
+/// A hero partway through a dash, moved by `dash_movement` until the timer runs out
+#[derive(Component, Debug, Clone)]
+pub struct Dashing {
+    pub velocity: Vec2,
+    pub timer: Timer,
+}
+
+/// Sent by player input and the enemy AI alike, so every hero's abilities go through `use_abilities`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UseAbility {
+    pub hero: Entity,
+    /// Index into the hero's `HeroAbilities`
+    pub slot: usize,
+    /// Which way the hero is facing, dashes go this way
+    pub direction: Vec2,
+}
+
+/// Runs after every system that sends `UseAbility`
+#[derive(SystemLabel, Clone, Hash, Debug, PartialEq, Eq)]
+pub struct UseAbilityLabel;
+
+pub struct AbilityPlugin;
+
+impl Plugin for AbilityPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<UseAbility>().add_system_set(
+            SystemSet::on_update(GameState::GamePlay)
+                .with_system(tick_stuns.before(UseAbilityLabel))
+                .with_system(tick_ability_cooldowns.before(UseAbilityLabel))
+                .with_system(
+                    player_abilities
+                        .after(PlayerInputLabel)
+                        .before(UseAbilityLabel),
+                )
+                .with_system(
+                    enemy_abilities
+                        .after(EnemyThinkLabel)
+                        .before(UseAbilityLabel),
+                )
+                .with_system(
+                    use_abilities
+                        .label(UseAbilityLabel)
+                        .before(ApplyDamageLabel),
+                )
+                .with_system(dash_movement.after(UseAbilityLabel)),
+        );
+    }
+}
+
+/// Gives `entity` the abilities its unit lists, ready to use straight away
+pub fn equip_abilities(
+    commands: &mut Commands,
+    entity: Entity,
+    def: &UnitDef,
+    abilities: &Abilities,
+) {
+    let slots = def
+        .abilities
+        .iter()
+        .filter_map(|name| match abilities.get(name) {
+            Some(ability) => {
+                let mut cooldown = Timer::from_seconds(ability.cooldown, false);
+                cooldown.tick(cooldown.duration());
+                Some(AbilitySlot {
+                    name: name.clone(),
+                    def: ability.clone(),
+                    cooldown,
+                })
+            }
+            None => {
+                error!(
+                    "{} has an ability called {} that doesn't exist",
+                    def.name, name
+                );
+                None
+            }
+        })
+        .collect();
+    commands.entity(entity).insert(HeroAbilities(slots));
+}
+
+fn tick_stuns(
+    mut commands: Commands,
+    mut units: Query<(Entity, &mut Stunned)>,
+    time: Res<GameTime>,
+) {
+    for (unit, mut stunned) in units.iter_mut() {
+        stunned.0.tick(time.delta());
+        if stunned.0.finished() {
+            commands.entity(unit).remove::<Stunned>();
+        }
+    }
+}
+
+fn tick_ability_cooldowns(mut heroes: Query<&mut HeroAbilities>, time: Res<GameTime>) {
+    for mut abilities in heroes.iter_mut() {
+        for slot in abilities.0.iter_mut() {
+            slot.cooldown.tick(time.delta());
+        }
+    }
+}
+
+/// Dashes go where the player aims, or where they walk when they aren't aiming
+fn player_abilities(
+    players: Query<(Entity, &PlayerIntent), With<Player>>,
+    mut use_ability: EventWriter<UseAbility>,
+) {
+    for (player, intent) in players.iter() {
+        let slot = match intent.ability {
+            Some(slot) => slot,
+            None => continue,
+        };
+        let direction = if intent.aim.length() > 0.1 {
+            intent.aim
+        } else {
+            intent.movement
+        };
+        use_ability.send(UseAbility {
+            hero: player,
+            slot,
+            direction: direction.try_normalize().unwrap_or_default(),
+        });
+    }
+}
+
+/// # Enemy abilities
+/// - Dash toward the brain's target when retreating, or when it's further off than the dash goes
+/// - Stun when an enemy hero or a couple of enemy units are in reach of it
+/// - Summon when defending, regrouping or retreating
+fn enemy_abilities(
+    enemies: Query<
+        (
+            Entity,
+            &GlobalTransform,
+            &Team,
+            &EnemyBrain,
+            &HeroAbilities,
+            &RespawnTimer,
+        ),
+        (With<Enemy>, Without<Stunned>),
+    >,
+    units: Query<
+        (
+            &GlobalTransform,
+            &Team,
+            Option<&Minion>,
+            Option<&RespawnTimer>,
+        ),
+        Or<(With<Player>, With<Enemy>, With<Minion>)>,
+    >,
+    physics_world: PhysicsWorld,
+    mut use_ability: EventWriter<UseAbility>,
+) {
+    for (hero, transform, team, brain, abilities, respawn) in enemies.iter() {
+        if respawn.is_dead {
+            continue;
+        }
+        let position = transform.translation.truncate();
+        let direction = (brain.target - position)
+            .try_normalize()
+            .unwrap_or_default();
+
+        let chosen = abilities.0.iter().position(|slot| {
+            slot.ready()
+                && match slot.def.effect {
+                    AbilityEffect::Dash { distance, .. } => {
+                        let wants = brain.goal == EnemyGoal::Retreat
+                            || position.distance(brain.target) > distance * 2.0;
+                        !brain.hold
+                            && wants
+                            && direction != Vec2::ZERO
+                            && has_line_of_sight(
+                                &physics_world,
+                                position,
+                                position + direction * distance,
+                            )
+                    }
+                    AbilityEffect::Stun { radius, .. } => {
+                        let (mut heroes, mut minions) = (0, 0);
+                        for (unit_transform, unit_team, minion, unit_respawn) in units.iter() {
+                            let dead = unit_respawn.map(|respawn| respawn.is_dead).unwrap_or(false);
+                            if unit_team == team
+                                || dead
+                                || unit_transform.translation.truncate().distance(position) > radius
+                            {
+                                continue;
+                            }
+                            if minion.is_some() {
+                                minions += 1;
+                            } else {
+                                heroes += 1;
+                            }
+                        }
+                        heroes > 0 || minions >= 2
+                    }
+                    AbilityEffect::Summon { .. } => matches!(
+                        brain.goal,
+                        EnemyGoal::Defend | EnemyGoal::Regroup | EnemyGoal::Retreat
+                    ),
+                }
+        });
+
+        if let Some(slot) = chosen {
+            use_ability.send(UseAbility {
+                hero,
+                slot,
+                direction,
+            });
+        }
+    }
+}
+
+/// Carries out every `UseAbility` whose ability is ready, then starts its cooldown.
+/// An ability with nothing to do, like a dash into a wall, stays ready
+fn use_abilities(
+    mut commands: Commands,
+    mut requests: EventReader<UseAbility>,
+    mut heroes: Query<
+        (
+            &GlobalTransform,
+            &Team,
+            &mut HeroAbilities,
+            &RespawnTimer,
+            Option<&CollisionShape>,
+        ),
+        Without<Stunned>,
+    >,
+    targets: Query<(Entity, &GlobalTransform, &Team, Option<&RespawnTimer>), With<Health>>,
+    mut spawners: Query<(&GlobalTransform, &Team, &mut Spawner)>,
+    physics_world: PhysicsWorld,
+    mut damage: EventWriter<DamageRequest>,
+    mut used: EventWriter<AbilityUsed>,
+) {
+    for request in requests.iter() {
+        let (transform, team, mut abilities, respawn, shape) = match heroes.get_mut(request.hero) {
+            Ok(hero) => hero,
+            Err(_) => continue,
+        };
+        if respawn.is_dead {
+            continue;
+        }
+        let slot = match abilities.0.get_mut(request.slot) {
+            Some(slot) if slot.ready() => slot,
+            _ => continue,
+        };
+        let position = transform.translation.truncate();
+
+        let done = match slot.def.effect {
+            AbilityEffect::Dash { distance, speed } => {
+                let radius = match shape {
+                    Some(CollisionShape::Sphere { radius }) => *radius,
+                    _ => 0.0,
+                };
+                let distance = dash_distance(
+                    &physics_world,
+                    position,
+                    request.direction,
+                    distance,
+                    radius,
+                );
+                if request.direction == Vec2::ZERO || speed <= 0.0 || distance <= 0.0 {
+                    false
+                } else {
+                    commands.entity(request.hero).insert(Dashing {
+                        velocity: request.direction * speed,
+                        timer: Timer::from_seconds(distance / speed, false),
+                    });
+                    true
+                }
+            }
+            AbilityEffect::Stun {
+                radius,
+                duration,
+                damage: amount,
+            } => {
+                for (target, target_transform, target_team, target_respawn) in targets.iter() {
+                    let dead = target_respawn
+                        .map(|respawn| respawn.is_dead)
+                        .unwrap_or(false);
+                    if target_team == team
+                        || dead
+                        || target_transform.translation.truncate().distance(position) > radius
+                    {
+                        continue;
+                    }
+                    commands
+                        .entity(target)
+                        .insert(Stunned(Timer::from_seconds(duration, false)))
+                        .remove::<Dashing>();
+                    if amount > 0.0 {
+                        damage.send(DamageRequest {
+                            source: request.hero,
+                            target,
+                            team: *team,
+                            amount,
+                            knockback: Vec2::ZERO,
+                        });
+                    }
+                }
+                true
+            }
+            AbilityEffect::Summon { count } => {
+                let closest = spawners
+                    .iter_mut()
+                    .filter(|(_, owner, _)| *owner == team)
+                    .min_by(|(a, ..), (b, ..)| {
+                        a.translation
+                            .truncate()
+                            .distance(position)
+                            .partial_cmp(&b.translation.truncate().distance(position))
+                            .unwrap_or(std::cmp::Ordering::Equal)
+                    });
+                match closest {
+                    Some((_, _, mut spawner)) => {
+                        spawner.queued += count;
+                        true
+                    }
+                    None => false,
+                }
+            }
+        };
+
+        if done {
+            slot.cooldown.reset();
+            used.send(AbilityUsed {
+                hero: request.hero,
+                team: *team,
+                slot: request.slot,
+                position,
+            });
+        }
+    }
+}
+
+/// How far a hero of `radius` can dash from `from` before it would run into a wall
+fn dash_distance(
+    physics_world: &PhysicsWorld,
+    from: Vec2,
+    direction: Vec2,
+    distance: f32,
+    radius: f32,
+) -> f32 {
+    physics_world
+        .ray_cast_with_filter(
+            from.extend(0.0),
+            (direction * (distance + radius)).extend(0.0),
+            false,
+            CollisionLayers::none()
+                .with_group(Layer::Wall)
+                .with_mask(Layer::Wall),
+            |_ent| true,
+        )
+        .map(|hit| (hit.collision_point.truncate().distance(from) - radius).clamp(0.0, distance))
+        .unwrap_or(distance)
+}
+
+fn dash_movement(
+    mut commands: Commands,
+    mut heroes: Query<(Entity, &mut Transform, &mut Dashing), Without<Stunned>>,
+    time: Res<GameTime>,
+) {
+    for (hero, mut transform, mut dash) in heroes.iter_mut() {
+        // The last step only covers what's left of the dash
+        let remaining = dash.timer.duration().saturating_sub(dash.timer.elapsed());
+        let step = time.delta().min(remaining).as_secs_f32();
+        transform.translation += (dash.velocity * step).extend(0.0);
+
+        dash.timer.tick(time.delta());
+        if dash.timer.finished() {
+            commands.entity(hero).remove::<Dashing>();
+        }
+    }
+}
@@ -0,0 +1,376 @@
+use bevy_inspector_egui::Inspectable;
+use heron::rapier_plugin::PhysicsWorld;
+
+use crate::prelude::*;
+
+/// Below this fraction of its health the hero starts thinking about retreating
+const RETREAT_HEALTH: f32 = 0.35;
+/// How far the hero looks for units when weighing up a fight
+const AWARENESS_RADIUS: f32 = 1.5;
+
+/// Ordered with `enemy_think` so heroes act on this frame's decision
+#[derive(SystemLabel, Clone, Hash, Debug, PartialEq, Eq)]
+pub struct EnemyThinkLabel;
+
+/// What an enemy hero is trying to do
+#[derive(Inspectable, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EnemyGoal {
+    /// Chase and shoot the closest visible enemy
+    Attack,
+    /// Take a spawner the hero's team doesn't own
+    Capture,
+    /// Go back to an owned spawner another team is capturing
+    Defend,
+    /// Run to the nearest owned spawner, or its spawn, to recover
+    Retreat,
+    /// Fall back to friendly minions when outnumbered
+    Regroup,
+}
+
+impl Default for EnemyGoal {
+    fn default() -> Self {
+        EnemyGoal::Attack
+    }
+}
+
+/// How much the hero wanted to pursue each goal, the highest wins
+#[derive(Inspectable, Clone, Copy, Debug, Default, PartialEq)]
+pub struct GoalScores {
+    pub attack: f32,
+    pub capture: f32,
+    pub defend: f32,
+    pub retreat: f32,
+    pub regroup: f32,
+}
+
+/// The last decision an enemy hero made, shown in the inspector
+#[derive(Component, Inspectable, Clone, Copy, Debug, Default)]
+pub struct EnemyBrain {
+    pub goal: EnemyGoal,
+    /// Where the goal is leading the hero
+    pub target: Vec2,
+    /// Whether the hero should stay where it is rather than walk to `target`
+    pub hold: bool,
+    pub scores: GoalScores,
+    /// Seconds until the hero reconsiders
+    pub reaction: f32,
+}
+
+/// A spawner as seen by the hero making a decision
+#[derive(Clone, Copy, Debug)]
+pub struct SpawnerFact {
+    pub position: Vec2,
+    pub owned: bool,
+    /// How far another team has got with taking this spawner
+    pub threat: f32,
+}
+
+/// Everything a hero takes into account, gathered from the world so `decide` stays a plain function
+#[derive(Clone, Debug, Default)]
+pub struct WorldFacts {
+    pub position: Vec2,
+    /// Current health as a fraction of max health
+    pub health: f32,
+    pub attack_range: f32,
+    /// The closest enemy in sight
+    pub visible_enemy: Option<Vec2>,
+    /// Where to go when nothing is in sight
+    pub fallback_target: Vec2,
+    pub home: Vec2,
+    pub spawners: Vec<SpawnerFact>,
+    /// Friendly minions and enemies within `AWARENESS_RADIUS`
+    pub nearby_friends: usize,
+    pub nearby_enemies: usize,
+    /// Center of the friendly minions anywhere on the map
+    pub friendly_minions: Option<Vec2>,
+}
+
+impl WorldFacts {
+    fn closest_spawner(&self, filter: impl Fn(&SpawnerFact) -> bool) -> Option<&SpawnerFact> {
+        self.spawners
+            .iter()
+            .filter(|spawner| filter(spawner))
+            .min_by(|a, b| {
+                a.position
+                    .distance(self.position)
+                    .partial_cmp(&b.position.distance(self.position))
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })
+    }
+}
+
+/// Scores every goal from `facts` and picks the best, ties go to the earliest goal in `EnemyGoal`.
+/// `aggression` above 1 favours attacking and holds off retreating for longer
+pub fn decide(facts: &WorldFacts, aggression: f32) -> EnemyBrain {
+    let distance_falloff = |target: Vec2| 1.0 / (1.0 + facts.position.distance(target));
+
+    let attack_target = facts.visible_enemy.unwrap_or(facts.fallback_target);
+    let attack = match facts.visible_enemy {
+        Some(enemy) => (0.6 + 0.4 * distance_falloff(enemy)) * aggression,
+        None => 0.1,
+    };
+
+    let capture_target = facts.closest_spawner(|spawner| !spawner.owned);
+    let capture = capture_target
+        .map(|spawner| 0.7 * distance_falloff(spawner.position))
+        .unwrap_or(0.0);
+
+    let defend_target = facts.closest_spawner(|spawner| spawner.owned && spawner.threat > 0.0);
+    let defend = defend_target
+        .map(|spawner| 0.5 + 0.5 * spawner.threat)
+        .unwrap_or(0.0);
+
+    let retreat_target = facts
+        .closest_spawner(|spawner| spawner.owned)
+        .map(|spawner| spawner.position)
+        .unwrap_or(facts.home);
+    let retreat_health = RETREAT_HEALTH / aggression.max(f32::EPSILON);
+    let retreat = if facts.health < retreat_health {
+        let danger = if facts.nearby_enemies > 0 { 1.0 } else { 0.6 };
+        (1.0 - facts.health / retreat_health) * 0.5 + 0.5 * danger
+    } else {
+        0.0
+    };
+
+    let outnumbered = facts.nearby_enemies > facts.nearby_friends + 1;
+    let regroup = match facts.friendly_minions {
+        Some(_) if outnumbered => 0.4 + 0.1 * (facts.nearby_enemies - facts.nearby_friends) as f32,
+        _ => 0.0,
+    }
+    .min(0.9);
+
+    let scores = GoalScores {
+        attack,
+        capture,
+        defend,
+        retreat,
+        regroup,
+    };
+
+    let options = [
+        (EnemyGoal::Attack, attack, attack_target),
+        (
+            EnemyGoal::Capture,
+            capture,
+            capture_target
+                .map(|spawner| spawner.position)
+                .unwrap_or(attack_target),
+        ),
+        (
+            EnemyGoal::Defend,
+            defend,
+            defend_target
+                .map(|spawner| spawner.position)
+                .unwrap_or(attack_target),
+        ),
+        (EnemyGoal::Retreat, retreat, retreat_target),
+        (
+            EnemyGoal::Regroup,
+            regroup,
+            facts.friendly_minions.unwrap_or(retreat_target),
+        ),
+    ];
+    let (goal, _, target) =
+        options.into_iter().fold(
+            options[0],
+            |best, option| if option.1 > best.1 { option } else { best },
+        );
+
+    // Shooting from range is safer than walking into the target
+    let hold = goal == EnemyGoal::Attack
+        && facts
+            .visible_enemy
+            .map(|enemy| enemy.distance(facts.position) < facts.attack_range * 0.8)
+            .unwrap_or(false);
+
+    EnemyBrain {
+        goal,
+        target,
+        hold,
+        scores,
+        reaction: 0.0,
+    }
+}
+
+/// Gathers the facts each enemy hero decides on
+pub fn enemy_think(
+    mut enemies: Query<
+        (
+            &mut EnemyBrain,
+            &GlobalTransform,
+            &Team,
+            &Health,
+            &MaxHealth,
+            &RangedAttack,
+            &RespawnTimer,
+        ),
+        With<Enemy>,
+    >,
+    spawners: Query<(&GlobalTransform, &Spawner, Option<&Team>)>,
+    units: Query<
+        (
+            &GlobalTransform,
+            &Team,
+            Option<&Minion>,
+            Option<&RespawnTimer>,
+        ),
+        (
+            Or<(With<Player>, With<Enemy>, With<Minion>)>,
+            Without<Spawner>,
+        ),
+    >,
+    player: Query<&GlobalTransform, With<Player>>,
+    map: Res<Assets<Map>>,
+    current_map: Res<CurrentMap>,
+    physics_world: PhysicsWorld,
+    difficulty: Res<Difficulty>,
+    time: Res<GameTime>,
+) {
+    let map = map.get(current_map.0.clone());
+
+    for (mut brain, transform, team, health, max_health, attack, respawn) in enemies.iter_mut() {
+        if respawn.is_dead {
+            continue;
+        }
+        brain.reaction -= time.delta_seconds();
+        if brain.reaction > 0.0 {
+            continue;
+        }
+        let position = transform.translation.truncate();
+
+        let spawners = spawners
+            .iter()
+            .map(|(transform, spawner, owner)| SpawnerFact {
+                position: transform.translation.truncate(),
+                owned: owner == Some(team),
+                // An owned spawner being taken loses its owner's progress before anyone else gains any
+                threat: match spawner.capturing {
+                    Some(capturing) if capturing == *team => 1.0 - spawner.capture_progress,
+                    Some(_) => spawner.capture_progress,
+                    None => 0.0,
+                },
+            })
+            .collect();
+
+        let mut facts = WorldFacts {
+            position,
+            health: health.0 / max_health.0.max(f32::EPSILON),
+            attack_range: attack.range,
+            fallback_target: player
+                .get_single()
+                .map(|player| player.translation.truncate())
+                .unwrap_or(position),
+            home: map.map(|map| map.hero_spawn(*team)).unwrap_or(position),
+            spawners,
+            ..default()
+        };
+
+        let mut minion_positions = Vec::new();
+        let mut closest_enemy: Option<(f32, Vec2)> = None;
+        for (unit_transform, unit_team, minion, unit_respawn) in units.iter() {
+            if unit_respawn.map(|respawn| respawn.is_dead).unwrap_or(false) {
+                continue;
+            }
+            let unit_position = unit_transform.translation.truncate();
+            let distance = unit_position.distance(position);
+            if unit_team == team {
+                if minion.is_some() {
+                    minion_positions.push(unit_position);
+                    if distance < AWARENESS_RADIUS {
+                        facts.nearby_friends += 1;
+                    }
+                }
+                continue;
+            }
+
+            if distance < AWARENESS_RADIUS {
+                facts.nearby_enemies += 1;
+            }
+            let closer = closest_enemy
+                .map(|(closest, _)| distance < closest)
+                .unwrap_or(true);
+            if closer && has_line_of_sight(&physics_world, position, unit_position) {
+                closest_enemy = Some((distance, unit_position));
+            }
+        }
+        facts.visible_enemy = closest_enemy.map(|(_, enemy)| enemy);
+        if !minion_positions.is_empty() {
+            facts.friendly_minions = Some(
+                minion_positions
+                    .iter()
+                    .fold(Vec2::ZERO, |sum, minion| sum + *minion)
+                    / minion_positions.len() as f32,
+            );
+        }
+
+        *brain = EnemyBrain {
+            reaction: difficulty.scaling.reaction_time,
+            ..decide(&facts, difficulty.scaling.aggression)
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spawner(x: f32, owned: bool, threat: f32) -> SpawnerFact {
+        SpawnerFact {
+            position: Vec2::new(x, 0.0),
+            owned,
+            threat,
+        }
+    }
+
+    #[test]
+    fn retreats_on_low_health() {
+        let facts = WorldFacts {
+            health: 0.05,
+            attack_range: 1.0,
+            visible_enemy: Some(Vec2::new(1.0, 0.0)),
+            spawners: vec![spawner(-2.0, true, 0.0)],
+            nearby_enemies: 1,
+            ..default()
+        };
+
+        let brain = decide(&facts, 1.0);
+        assert_eq!(brain.goal, EnemyGoal::Retreat);
+        assert_eq!(brain.target, Vec2::new(-2.0, 0.0));
+    }
+
+    #[test]
+    fn defends_a_spawner_being_taken() {
+        let facts = WorldFacts {
+            health: 1.0,
+            attack_range: 1.0,
+            spawners: vec![spawner(1.0, false, 0.0), spawner(3.0, true, 0.5)],
+            ..default()
+        };
+
+        let brain = decide(&facts, 1.0);
+        assert_eq!(brain.goal, EnemyGoal::Defend);
+        assert_eq!(brain.target, Vec2::new(3.0, 0.0));
+    }
+
+    #[test]
+    fn aggression_shifts_retreat_to_attack() {
+        let facts = WorldFacts {
+            health: 0.2,
+            attack_range: 1.0,
+            visible_enemy: Some(Vec2::new(3.0, 0.0)),
+            spawners: vec![spawner(-2.0, true, 0.0)],
+            nearby_enemies: 1,
+            ..default()
+        };
+
+        let cautious = decide(&facts, 0.5);
+        assert_eq!(cautious.goal, EnemyGoal::Retreat);
+        assert!(cautious.scores.retreat > cautious.scores.attack);
+
+        let aggressive = decide(&facts, 1.5);
+        assert_eq!(aggressive.goal, EnemyGoal::Attack);
+        assert_eq!(aggressive.target, Vec2::new(3.0, 0.0));
+        assert!(aggressive.scores.attack > cautious.scores.attack);
+        assert!(aggressive.scores.retreat < cautious.scores.retreat);
+    }
+}
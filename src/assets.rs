@@ -1,7 +1,12 @@
+use bevy::{
+    asset::{AssetLoader, HandleId, LoadContext, LoadedAsset},
+    reflect::TypeUuid,
+    utils::{BoxedFuture, HashMap},
+};
 use bevy_asset_loader::AssetCollection;
 use serde::Deserialize;
 
-use crate::prelude::*;
+use crate::{particles::ParticleSpawner, prelude::*, unit::UnitDef};
 
 pub struct GameAssetsPlugin;
 
@@ -9,7 +14,11 @@ pub const PIXEL_SIZE: f32 = 200.00;
 
 impl Plugin for GameAssetsPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system_set(SystemSet::on_exit(GameState::Splash).with_system(load_graphics))
+        app.add_asset::<GraphicsDesc>()
+            .init_asset_loader::<GraphicsDescLoader>()
+            .add_system_set(SystemSet::on_exit(GameState::Splash).with_system(load_graphics))
+            .init_resource::<UnitSprites>()
+            .add_system(build_unit_sprites)
             .add_system(rotate)
             .add_system_to_stage(CoreStage::PostUpdate, flash_red)
             .add_system(animate_frames);
@@ -19,13 +28,16 @@ impl Plugin for GameAssetsPlugin {
 #[derive(Component)]
 pub struct Rotate;
 
-fn rotate(mut graphics: Query<&mut Transform, With<Rotate>>, time: Res<Time>) {
+fn rotate(mut graphics: Query<&mut Transform, With<Rotate>>, time: Res<GameTime>) {
     for mut transform in graphics.iter_mut() {
         (*transform).rotate(Quat::from_axis_angle(Vec3::Z, 5.1 * time.delta_seconds()));
     }
 }
 
-fn animate_frames(mut graphics: Query<(&mut TextureAtlasSprite, &mut Animation)>, time: Res<Time>) {
+fn animate_frames(
+    mut graphics: Query<(&mut TextureAtlasSprite, &mut Animation)>,
+    time: Res<GameTime>,
+) {
     for (mut sprite, mut animation) in graphics.iter_mut() {
         sprite.flip_y = animation.flip_y;
         if !animation.playing && !animation.playing_alt {
@@ -68,20 +80,20 @@ pub struct OurAssets {
     #[asset(path = "bullets.png")]
     pub bullet: Handle<Image>,
 
-    #[asset(path = "chicken.png")]
-    pub chicken: Handle<Image>,
+    #[asset(path = "bullets.anim")]
+    pub bullet_desc: Handle<GraphicsDesc>,
 
-    #[asset(path = "chicken_minion.png")]
-    pub chicken_minion: Handle<Image>,
+    #[asset(path = "main.factions")]
+    pub factions: Handle<Factions>,
 
-    #[asset(path = "dog_minion.png")]
-    pub dog_minion: Handle<Image>,
+    #[asset(path = "main.tuning")]
+    pub tuning: Handle<GameTuning>,
 
     #[asset(path = "building.png")]
     pub building: Handle<Image>,
 
-    #[asset(path = "dog.png")]
-    pub dog: Handle<Image>,
+    #[asset(path = "building.anim")]
+    pub building_desc: Handle<GraphicsDesc>,
 
     #[asset(path = "awesome.png")]
     pub dog_spawner: Handle<Image>,
@@ -92,8 +104,8 @@ pub struct OurAssets {
     #[asset(path = "Background.png")]
     pub background: Handle<Image>,
 
-    #[asset(path = "main.map")]
-    pub map: Handle<Map>,
+    #[asset(path = "menu.particles")]
+    pub menu_particles: Handle<ParticleSpawner>,
 
     #[asset(path = "bip-bop.ogg")]
     pub background_music: Handle<bevy_kira_audio::AudioSource>,
@@ -119,33 +131,50 @@ impl SpriteDesc {
     }
 }
 
-#[derive(Deserialize)]
+/// The frames of an animation, loaded from a `.anim` file
+#[derive(Deserialize, TypeUuid)]
+#[uuid = "6a0d9e3b-7f25-4c1a-b8d4-3e9f0c2a5b71"]
 pub struct GraphicsDesc {
-    frames: Vec<SpriteDesc>,
+    pub frames: Vec<SpriteDesc>,
 }
 
-pub struct ChickenWalkFrames {
-    pub frames: Vec<TextureAtlasSprite>,
-    pub alt_frames: Vec<TextureAtlasSprite>,
-    pub texture: Handle<TextureAtlas>,
-}
+#[derive(Default)]
+pub struct GraphicsDescLoader;
+
+impl AssetLoader for GraphicsDescLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let desc = ron::de::from_bytes::<GraphicsDesc>(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(desc));
+            Ok(())
+        })
+    }
 
-pub struct DogWalkFrames {
-    pub frames: Vec<TextureAtlasSprite>,
-    pub alt_frames: Vec<TextureAtlasSprite>,
-    pub texture: Handle<TextureAtlas>,
+    fn extensions(&self) -> &[&str] {
+        &["anim"]
+    }
 }
 
-pub struct PuppyWalkFrames {
+/// Walk frames and attack frames of a unit, cut from its sprite sheet
+#[derive(Clone)]
+pub struct UnitFrames {
     pub frames: Vec<TextureAtlasSprite>,
     pub alt_frames: Vec<TextureAtlasSprite>,
     pub texture: Handle<TextureAtlas>,
 }
 
-pub struct ChickWalkFrames {
-    pub frames: Vec<TextureAtlasSprite>,
-    pub alt_frames: Vec<TextureAtlasSprite>,
-    pub texture: Handle<TextureAtlas>,
+/// The frames of every loaded `UnitDef`, built once its sprite sheet is ready
+#[derive(Default)]
+pub struct UnitSprites(HashMap<HandleId, UnitFrames>);
+
+impl UnitSprites {
+    pub fn get(&self, unit: &Handle<UnitDef>) -> Option<&UnitFrames> {
+        self.0.get(&unit.id)
+    }
 }
 
 pub struct BulletFrames {
@@ -159,7 +188,7 @@ pub struct BuildingFrames {
     pub texture: Handle<TextureAtlas>,
 }
 
-fn flash_red(mut sprites: Query<(&mut TextureAtlasSprite, &mut DamageFlash)>, time: Res<Time>) {
+fn flash_red(mut sprites: Query<(&mut TextureAtlasSprite, &mut DamageFlash)>, time: Res<GameTime>) {
     for (mut sprite, mut flash) in sprites.iter_mut() {
         sprite.color = Color::WHITE;
         if !flash.timer.finished() {
@@ -169,14 +198,10 @@ fn flash_red(mut sprites: Query<(&mut TextureAtlasSprite, &mut DamageFlash)>, ti
     }
 }
 
-fn parse_animation(file_contents: &str, atlas: &mut TextureAtlas) -> Vec<TextureAtlasSprite> {
-    let desc: GraphicsDesc = ron::from_str(file_contents).unwrap_or_else(|e| {
-        panic!("Failed to load config: {}", e);
-    });
-
+fn add_frames(descs: &[SpriteDesc], atlas: &mut TextureAtlas) -> Vec<TextureAtlasSprite> {
     let mut frames = Vec::new();
 
-    for desc in desc.frames.iter() {
+    for desc in descs.iter() {
         let mut sprite = TextureAtlasSprite::new(atlas.add_texture(desc.to_atlas_rect()));
         //Set the size to be proportional to the source rectangle
         sprite.custom_size = Some(Vec2::new(
@@ -192,92 +217,79 @@ fn parse_animation(file_contents: &str, atlas: &mut TextureAtlas) -> Vec<Texture
 
 fn load_graphics(
     mut commands: Commands,
-    assets: ResMut<OurAssets>,
+    assets: Res<OurAssets>,
     mut texture_atlases: ResMut<Assets<TextureAtlas>>,
     images: Res<Assets<Image>>,
+    descs: Res<Assets<GraphicsDesc>>,
 ) {
-    //unwrap safe because asset loader
-    let chicken_image = images.get(assets.chicken.clone()).unwrap();
-    let mut chicken_atlas = TextureAtlas::new_empty(assets.chicken.clone(), chicken_image.size());
-
-    let chick_image = images.get(assets.chicken_minion.clone()).unwrap();
-    let mut chick_atlas =
-        TextureAtlas::new_empty(assets.chicken_minion.clone(), chick_image.size());
-
-    let dog_image = images.get(assets.dog.clone()).unwrap();
-    let mut dog_atlas = TextureAtlas::new_empty(assets.dog.clone(), dog_image.size());
-
-    let puppy_image = images.get(assets.dog_minion.clone()).unwrap();
-    let mut puppy_atlas = TextureAtlas::new_empty(assets.dog_minion.clone(), puppy_image.size());
-
-    let bullets_image = images.get(assets.bullet.clone()).unwrap();
-    let mut bullets_atlas = TextureAtlas::new_empty(assets.bullet.clone(), bullets_image.size());
-
-    let buildings_image = images.get(assets.building.clone()).unwrap();
-    let mut buildings_atlas =
-        TextureAtlas::new_empty(assets.building.clone(), buildings_image.size());
-
-    let chicken_walk = parse_animation(
-        include_str!("../assets/chicken_walk.ron"),
-        &mut chicken_atlas,
-    );
-    let chicken_shoot = parse_animation(
-        include_str!("../assets/chicken_shoot.ron"),
-        &mut chicken_atlas,
-    );
-
-    let chick_walk = parse_animation(include_str!("../assets/chick_walk.ron"), &mut chick_atlas);
-    let chick_attack =
-        parse_animation(include_str!("../assets/chick_attack.ron"), &mut chick_atlas);
-
-    let dog_walk = parse_animation(include_str!("../assets/dog_walk.ron"), &mut dog_atlas);
-    let dog_attack = parse_animation(include_str!("../assets/dog_shoot.ron"), &mut dog_atlas);
-
-    let puppy_walk = parse_animation(include_str!("../assets/puppy_walk.ron"), &mut puppy_atlas);
-    let puppy_attack = parse_animation(include_str!("../assets/puppy_shoot.ron"), &mut puppy_atlas);
-
-    let bullet_frames = parse_animation(include_str!("../assets/bullets.ron"), &mut bullets_atlas);
-    let building_frames =
-        parse_animation(include_str!("../assets/building.ron"), &mut buildings_atlas);
-
-    let chicken_handle = texture_atlases.add(chicken_atlas);
-    let chick_handle = texture_atlases.add(chick_atlas);
-    let dog_handle = texture_atlases.add(dog_atlas);
-    let puppy_handle = texture_atlases.add(puppy_atlas);
-    let bullet_handle = texture_atlases.add(bullets_atlas);
-    let building_handle = texture_atlases.add(buildings_atlas);
-
-    commands.insert_resource(ChickenWalkFrames {
-        frames: chicken_walk,
-        alt_frames: chicken_shoot,
-        texture: chicken_handle,
-    });
-
-    commands.insert_resource(ChickWalkFrames {
-        frames: chick_walk,
-        alt_frames: chick_attack,
-        texture: chick_handle,
-    });
-
-    commands.insert_resource(DogWalkFrames {
-        frames: dog_walk,
-        alt_frames: dog_attack,
-        texture: dog_handle,
-    });
-
-    commands.insert_resource(PuppyWalkFrames {
-        frames: puppy_walk,
-        alt_frames: puppy_attack,
-        texture: puppy_handle,
-    });
-
-    commands.insert_resource(BulletFrames {
-        frames: bullet_frames,
-        texture: bullet_handle,
-    });
-
-    commands.insert_resource(BuildingFrames {
-        frames: building_frames,
-        texture: building_handle,
-    });
+    let mut build = |image: &Handle<Image>, desc: &Handle<GraphicsDesc>| {
+        build_frames(image, desc, &images, &descs, &mut texture_atlases)
+    };
+
+    match build(&assets.bullet, &assets.bullet_desc) {
+        Ok((frames, texture)) => commands.insert_resource(BulletFrames { frames, texture }),
+        Err(e) => error!("Failed to build bullet frames: {:?}", e),
+    }
+
+    match build(&assets.building, &assets.building_desc) {
+        Ok((frames, texture)) => commands.insert_resource(BuildingFrames { frames, texture }),
+        Err(e) => error!("Failed to build building frames: {:?}", e),
+    }
+}
+
+fn build_frames(
+    image: &Handle<Image>,
+    desc: &Handle<GraphicsDesc>,
+    images: &Assets<Image>,
+    descs: &Assets<GraphicsDesc>,
+    texture_atlases: &mut Assets<TextureAtlas>,
+) -> anyhow::Result<(Vec<TextureAtlasSprite>, Handle<TextureAtlas>)> {
+    let image_size = images
+        .get(image)
+        .context("sprite sheet isn't loaded")?
+        .size();
+    let desc = descs.get(desc).context("animation isn't loaded")?;
+
+    let mut atlas = TextureAtlas::new_empty(image.clone(), image_size);
+    let frames = add_frames(&desc.frames, &mut atlas);
+    Ok((frames, texture_atlases.add(atlas)))
+}
+
+/// Cuts the frames of each unit out of its sprite sheet as soon as both are loaded,
+/// and again whenever the unit file changes
+fn build_unit_sprites(
+    mut sprites: ResMut<UnitSprites>,
+    mut events: EventReader<AssetEvent<UnitDef>>,
+    units: Res<Assets<UnitDef>>,
+    images: Res<Assets<Image>>,
+    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+) {
+    for event in events.iter() {
+        if let AssetEvent::Modified { handle } | AssetEvent::Removed { handle } = event {
+            sprites.0.remove(&handle.id);
+        }
+    }
+
+    for (id, def) in units.iter() {
+        if sprites.0.contains_key(&id) {
+            continue;
+        }
+        let image = match images.get(def.texture.clone()) {
+            Some(image) => image,
+            None => continue,
+        };
+
+        let mut atlas = TextureAtlas::new_empty(def.texture.clone(), image.size());
+        let frames = add_frames(&def.walk_frames, &mut atlas);
+        let alt_frames = add_frames(&def.attack_frames, &mut atlas);
+
+        sprites.0.insert(
+            id,
+            UnitFrames {
+                frames,
+                alt_frames,
+                texture: texture_atlases.add(atlas),
+            },
+        );
+    }
 }
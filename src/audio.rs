@@ -14,6 +14,7 @@ impl Plugin for GameAudioPlugin {
                     .with_system(play_background_music.after(create_audio_state)),
             )
             .add_system_set(SystemSet::on_update(GameState::MainMenu).with_system(update_volume))
+            .add_system_set(SystemSet::on_update(GameState::Paused).with_system(update_volume))
             .add_audio_channel::<Background>();
     }
 }
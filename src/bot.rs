@@ -0,0 +1,108 @@
+use heron::rapier_plugin::PhysicsWorld;
+
+use crate::{player::PlayerInputLabel, prelude::*};
+
+/// Drives a player's `PlayerIntent` instead of the keyboard and gamepad
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct Bot {
+    /// Hostile units closer than this are shot at
+    pub range: f32,
+}
+
+impl Default for Bot {
+    fn default() -> Self {
+        Self { range: 2.5 }
+    }
+}
+
+pub struct BotPlugin;
+
+impl Plugin for BotPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(
+            SystemSet::on_update(GameState::GamePlay)
+                .with_system(bot_intent.label(PlayerInputLabel)),
+        )
+        .register_type::<Bot>();
+    }
+}
+
+/// Hands every newly spawned player over to a bot, for AI vs AI matches and attract mode
+pub fn take_over_player(
+    mut commands: Commands,
+    players: Query<Entity, (Added<Player>, Without<Bot>)>,
+) {
+    for player in players.iter() {
+        commands.entity(player).insert(Bot::default());
+    }
+}
+
+/// # Bot AI
+/// - Walks toward the closest visible enemy, enemy minion, or spawner it doesn't own
+/// - Falls back to the closest spawner it doesn't own when nothing is visible
+/// - Shoots at the closest visible enemy or enemy minion in range
+fn bot_intent(
+    mut bots: Query<(
+        &GlobalTransform,
+        &Team,
+        &Bot,
+        &RespawnTimer,
+        &mut PlayerIntent,
+    )>,
+    targets: Query<
+        (&GlobalTransform, Option<&Team>, Option<&Spawner>),
+        Or<(With<Spawner>, With<Player>, With<Enemy>, With<Minion>)>,
+    >,
+    physics_world: PhysicsWorld,
+) {
+    for (global_transform, team, bot, respawn, mut intent) in bots.iter_mut() {
+        intent.movement = Vec2::ZERO;
+        intent.aim = Vec2::ZERO;
+        if respawn.is_dead {
+            continue;
+        }
+
+        let position = global_transform.translation.truncate();
+
+        let hostile: Vec<(GlobalTransform, bool)> = targets
+            .iter()
+            .filter(|(_, target_team, _)| *target_team != Some(team))
+            .map(|(transform, _, spawner)| (*transform, spawner.is_some()))
+            .collect();
+
+        let visible: Vec<(GlobalTransform, bool)> = hostile
+            .iter()
+            .filter(|(transform, _)| {
+                has_line_of_sight(&physics_world, position, transform.translation.truncate())
+            })
+            .cloned()
+            .collect();
+
+        let move_target = find_closest(position, visible.iter().map(|(t, _)| *t)).or_else(|| {
+            find_closest(
+                position,
+                hostile
+                    .iter()
+                    .filter(|(_, is_spawner)| *is_spawner)
+                    .map(|(t, _)| *t),
+            )
+        });
+        if let Some(target) = move_target {
+            intent.movement = (target - position).try_normalize().unwrap_or_default();
+        }
+
+        let shoot_target = find_closest(
+            position,
+            visible
+                .iter()
+                .filter(|(_, is_spawner)| !is_spawner)
+                .map(|(t, _)| *t),
+        );
+        if let Some(target) = shoot_target {
+            if Vec2::distance(target, position) <= bot.range {
+                intent.aim = (target - position).try_normalize().unwrap_or_default();
+            }
+        }
+    }
+}
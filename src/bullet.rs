@@ -1,51 +1,258 @@
-use crate::prelude::*;
+use serde::Deserialize;
+
+use crate::{
+    assets::{BulletFrames, Rotate},
+    damage::{ApplyDamageLabel, DamageRequest},
+    events::BulletFired,
+    map::Wall,
+    prelude::*,
+};
 pub struct BulletPlugin;
 
 impl Plugin for BulletPlugin {
     fn build(&self, app: &mut App) {
         app.add_system(bullet_fly)
-            .add_system(delete_bullet)
-            .add_system(bullet_damage);
+            .add_system(bullet_cull.after(bullet_fly))
+            .add_system(bullet_damage.before(ApplyDamageLabel));
+    }
+}
+
+/// Bullets fly this much further than their attack's range, so a shot at the edge of it still lands
+pub const OVERSHOOT: f32 = 1.25;
+
+/// Seconds a bullet lasts when nothing says otherwise
+pub const DEFAULT_LIFETIME: f32 = 5.0;
+
+/// What a bullet does when it hits a wall
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub enum WallBehavior {
+    Stop,
+    /// Bounces off up to `bounces` times, then stops
+    Ricochet {
+        bounces: u32,
+    },
+    Pass,
+}
+
+impl Default for WallBehavior {
+    fn default() -> Self {
+        WallBehavior::Stop
+    }
+}
+
+/// Past `start` of its range a bullet's damage drops off, down to `min` of it at full range
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+pub struct Falloff {
+    pub start: f32,
+    pub min: f32,
+}
+
+impl Falloff {
+    /// How much of its damage a bullet deals after flying `traveled` of `range`
+    pub fn scale(&self, traveled: f32, range: f32) -> f32 {
+        if range <= 0.0 || self.start >= 1.0 {
+            return 1.0;
+        }
+        let t = ((traveled / range - self.start) / (1.0 - self.start)).clamp(0.0, 1.0);
+        1.0 + (self.min - 1.0) * t
     }
 }
 
+/// What a single bullet does once it's fired
+#[derive(Clone, Copy, Debug)]
+pub struct BulletStats {
+    pub damage: f32,
+    pub speed: f32,
+    pub size: f32,
+    /// How many units it goes through before it stops
+    pub pierce: u32,
+    /// How far it flies before it's removed
+    pub range: f32,
+    /// Seconds it lasts before it's removed
+    pub lifetime: f32,
+    pub falloff: Option<Falloff>,
+    pub walls: WallBehavior,
+}
+
+impl From<&RangedAttack> for BulletStats {
+    fn from(attack: &RangedAttack) -> Self {
+        Self {
+            damage: attack.damage,
+            speed: attack.bullet_speed,
+            size: 0.1,
+            pierce: 0,
+            range: attack.range * OVERSHOOT,
+            lifetime: DEFAULT_LIFETIME,
+            falloff: None,
+            walls: WallBehavior::Stop,
+        }
+    }
+}
+
+/// Fires a bullet for `team` from `transform` along `direction`.
+/// `frame` picks the bullet's sprite when rendering
+pub fn spawn_bullet(
+    commands: &mut Commands,
+    fired: &mut EventWriter<BulletFired>,
+    transform: Transform,
+    team: Team,
+    stats: BulletStats,
+    direction: Vec2,
+    sprite: Option<(&BulletFrames, usize)>,
+) -> Entity {
+    let bullet = commands
+        .spawn_bundle(TransformBundle::from_transform(transform))
+        .insert(Bullet {
+            speed: stats.speed,
+            damage: stats.damage,
+            direction,
+            pierce: stats.pierce,
+            hits: Vec::new(),
+            traveled: 0.0,
+            range: stats.range,
+            lifetime: Timer::from_seconds(stats.lifetime, false),
+            falloff: stats.falloff,
+            walls: stats.walls,
+        })
+        .insert(Rotate)
+        .insert(team)
+        .insert(RigidBody::Sensor)
+        .insert(CollisionShape::Sphere {
+            radius: stats.size / 2.0,
+        })
+        .insert(RotationConstraints::lock())
+        .insert(
+            CollisionLayers::all_masks::<Layer>()
+                .with_group(Layer::Bullet)
+                // Every AI team shares the Enemy layer, `bullet_damage` skips the shooter's own team
+                .without_mask(Layer::Bullet),
+        )
+        .insert(Collisions::default())
+        .insert(Name::new("Bullet"))
+        .id();
+    if let Some((bullets, frame)) = sprite {
+        commands
+            .entity(bullet)
+            .insert(bullets.frames[frame].clone())
+            .insert(bullets.texture.clone())
+            .insert(Visibility::default());
+    }
+    fired.send(BulletFired {
+        bullet,
+        team,
+        position: transform.translation.truncate(),
+        direction,
+    });
+    bullet
+}
+
+/// Bullets hurt each enemy unit they touch once, going through as many as they pierce.
+/// Walls are handled by the bullet's `WallBehavior`, anything else stops it
 pub fn bullet_damage(
-    mut entities: Query<(&mut Health, &ChickenOrDog, Option<&mut DamageFlash>)>,
-    bullets: Query<(&Collisions, &ChickenOrDog), With<Bullet>>,
+    mut commands: Commands,
+    mut bullets: Query<(Entity, &Collisions, &Team, &GlobalTransform, &mut Bullet)>,
+    units: Query<&Team, With<Health>>,
+    walls: Query<&Wall>,
+    mut damage: EventWriter<DamageRequest>,
+    tuning: Res<GameTuning>,
 ) {
-    bullets
-        .iter()
-        .flat_map(|(collisions, origin_team)| {
-            collisions
-                .entities()
-                .map(move |collision| (collision, origin_team))
-        })
-        .for_each(|(entity, origin_team)| {
-            if let Ok((mut health, entity_team, damage)) = entities.get_mut(entity) {
-                if origin_team != entity_team {
-                    health.0 -= 1.0;
-                    if let Some(mut damage) = damage {
-                        damage.timer = Timer::from_seconds(0.1, true);
+    for (bullet_ent, collisions, team, transform, mut bullet) in bullets.iter_mut() {
+        let mut stopped = false;
+        for target in collisions.entities() {
+            if bullet.hits.contains(&target) {
+                continue;
+            }
+            match units.get(target) {
+                Ok(target_team) if target_team == team => continue,
+                Ok(_) => {}
+                Err(_) => {
+                    if let Ok(wall) = walls.get(target) {
+                        stopped = hit_wall(&mut bullet, wall, transform.translation.truncate());
+                    } else {
+                        stopped = true;
                     }
+                    if stopped {
+                        break;
+                    }
+                    continue;
                 }
             }
-        });
+
+            let falloff = bullet
+                .falloff
+                .map(|falloff| falloff.scale(bullet.traveled, bullet.range))
+                .unwrap_or(1.0);
+            damage.send(DamageRequest {
+                source: bullet_ent,
+                target,
+                team: *team,
+                amount: bullet.damage * falloff,
+                knockback: bullet.direction * tuning.damage.bullet_knockback,
+            });
+            bullet.hits.push(target);
+            if bullet.pierce == 0 {
+                stopped = true;
+                break;
+            }
+            bullet.pierce -= 1;
+        }
+        if stopped {
+            commands.entity(bullet_ent).despawn_recursive();
+        }
+    }
 }
 
-fn delete_bullet(mut commands: Commands, bullets: Query<(&Collisions, Entity), With<Bullet>>) {
-    let bullets_to_delete = bullets.iter().filter_map(|(collisions, bullet_ent)| {
-        if !collisions.is_empty() {
-            Some(bullet_ent)
-        } else {
-            None
+/// Returns whether the wall stops the bullet
+fn hit_wall(bullet: &mut Bullet, wall: &Wall, position: Vec2) -> bool {
+    match bullet.walls {
+        WallBehavior::Stop => true,
+        WallBehavior::Pass => false,
+        WallBehavior::Ricochet { bounces } => {
+            let normal = wall.0.normal_at(position);
+            // Still inside the wall after bouncing off it last frame
+            if bullet.direction.dot(normal) >= 0.0 {
+                return false;
+            }
+            if bounces == 0 {
+                return true;
+            }
+            bullet.direction -= 2.0 * bullet.direction.dot(normal) * normal;
+            bullet.walls = WallBehavior::Ricochet {
+                bounces: bounces - 1,
+            };
+            false
         }
-    });
+    }
+}
 
-    bullets_to_delete.for_each(|ent| commands.entity(ent).despawn());
+fn bullet_fly(mut bullets: Query<(&mut Transform, &mut Bullet)>, time: Res<GameTime>) {
+    for (mut transform, mut bullet) in bullets.iter_mut() {
+        let step = bullet.speed * time.delta_seconds();
+        transform.translation += bullet.direction.extend(0.0) * step;
+        bullet.traveled += step;
+        bullet.lifetime.tick(time.delta());
+    }
 }
 
-fn bullet_fly(mut bullets: Query<(&mut Transform, &Bullet)>, time: Res<Time>) {
-    for (mut transform, bullet) in bullets.iter_mut() {
-        transform.translation += bullet.direction.extend(0.0) * time.delta_seconds();
+/// Removes bullets that have flown their range, outlived their lifetime or left the map
+fn bullet_cull(
+    mut commands: Commands,
+    bullets: Query<(Entity, &GlobalTransform, &Bullet)>,
+    maps: Res<Assets<Map>>,
+    current_map: Option<Res<CurrentMap>>,
+) {
+    let bounds = current_map
+        .and_then(|current_map| maps.get(current_map.0.clone()))
+        .map(|map| map.bounds)
+        // Maps without walls have no bounds to leave
+        .filter(|bounds| bounds.min != bounds.max);
+
+    for (bullet_ent, transform, bullet) in bullets.iter() {
+        let outside = bounds
+            .map(|bounds| !bounds.contains(transform.translation.truncate()))
+            .unwrap_or(false);
+        if bullet.traveled >= bullet.range || bullet.lifetime.finished() || outside {
+            commands.entity(bullet_ent).despawn_recursive();
+        }
     }
 }
@@ -0,0 +1,210 @@
+use serde::Deserialize;
+
+use crate::{economy::LastAttacker, events::DamageDealt, prelude::*};
+
+/// Everything that hurts sends one of these rather than touching `Health`,
+/// so armor, invulnerability and feedback work the same for every source
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DamageRequest {
+    /// The bullet or unit dealing the damage
+    pub source: Entity,
+    pub target: Entity,
+    /// The attacking team, which never hurts its own units
+    pub team: Team,
+    pub amount: f32,
+    /// Velocity the hit pushes the target with
+    pub knockback: Vec2,
+}
+
+/// How hits are softened and what they feel like, part of `GameTuning`
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct DamageTuning {
+    /// Seconds a unit can't be hurt again after it's hit
+    pub invulnerability: f32,
+    /// Seconds a unit flashes red after it's hit
+    pub flash_time: f32,
+    /// How fast bullets and melee hits push what they hit
+    pub bullet_knockback: f32,
+    pub melee_knockback: f32,
+    /// How much of its knockback a unit loses each second
+    pub knockback_decay: f32,
+}
+
+impl Default for DamageTuning {
+    fn default() -> Self {
+        Self {
+            invulnerability: 0.1,
+            flash_time: 0.1,
+            bullet_knockback: 0.3,
+            melee_knockback: 0.5,
+            knockback_decay: 8.0,
+        }
+    }
+}
+
+/// Soaks up damage, `flat` comes off every hit before `resistance` takes its share of the rest
+#[derive(Component, Debug, Clone, Copy, Default, Deserialize)]
+pub struct Armor {
+    #[serde(default)]
+    pub flat: f32,
+    #[serde(default)]
+    pub resistance: f32,
+}
+
+impl Armor {
+    pub fn reduce(&self, amount: f32) -> f32 {
+        (amount - self.flat).max(0.0) * (1.0 - self.resistance.clamp(0.0, 1.0))
+    }
+}
+
+/// A unit can't be hurt until this runs out
+#[derive(Component, Debug, Clone, Default)]
+pub struct Invulnerable(pub Timer);
+
+/// Velocity from being hit, on top of however the unit moves itself
+#[derive(Component, Debug, Clone, Copy, Default)]
+pub struct Knockback(pub Vec2);
+
+/// Runs after every system that sends `DamageRequest`s, so hits land on the frame they're made
+#[derive(SystemLabel, Clone, Hash, Debug, PartialEq, Eq)]
+pub struct ApplyDamageLabel;
+
+pub struct DamagePlugin;
+
+impl Plugin for DamagePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<DamageRequest>()
+            .add_system(tick_invulnerability.before(ApplyDamageLabel))
+            .add_system(apply_damage.label(ApplyDamageLabel))
+            .add_system(apply_knockback.after(ApplyDamageLabel));
+    }
+}
+
+fn tick_invulnerability(mut units: Query<&mut Invulnerable>, time: Res<GameTime>) {
+    for mut invulnerable in units.iter_mut() {
+        invulnerable.0.tick(time.delta());
+    }
+}
+
+fn apply_damage(
+    mut requests: EventReader<DamageRequest>,
+    mut targets: Query<(
+        &mut Health,
+        &Team,
+        Option<&Armor>,
+        Option<&mut Invulnerable>,
+        Option<&mut Knockback>,
+        Option<&mut DamageFlash>,
+        Option<&mut LastAttacker>,
+    )>,
+    mut damage_dealt: EventWriter<DamageDealt>,
+    tuning: Res<GameTuning>,
+) {
+    let damage = &tuning.damage;
+    // Every pellet of a shotgun blast lands on the same frame, so i-frames only stop later hits
+    let mut hit_this_frame = Vec::new();
+
+    for request in requests.iter() {
+        let (mut health, team, armor, invulnerable, knockback, flash, last_attacker) =
+            match targets.get_mut(request.target) {
+                Ok(target) => target,
+                Err(_) => continue,
+            };
+        if *team == request.team {
+            continue;
+        }
+        if let Some(invulnerable) = &invulnerable {
+            if !invulnerable.0.finished() && !hit_this_frame.contains(&request.target) {
+                continue;
+            }
+        }
+        hit_this_frame.push(request.target);
+
+        let amount = armor
+            .map(|armor| armor.reduce(request.amount))
+            .unwrap_or(request.amount);
+        health.0 -= amount;
+        damage_dealt.send(DamageDealt {
+            source: request.source,
+            target: request.target,
+            amount,
+        });
+
+        if let Some(mut invulnerable) = invulnerable {
+            invulnerable.0 = Timer::from_seconds(damage.invulnerability, false);
+        }
+        if let Some(mut knockback) = knockback {
+            knockback.0 += request.knockback;
+        }
+        if let Some(mut flash) = flash {
+            flash.timer = Timer::from_seconds(damage.flash_time, false);
+        }
+        if let Some(mut last_attacker) = last_attacker {
+            last_attacker.0 = Some(request.team);
+        }
+    }
+}
+
+fn apply_knockback(
+    mut units: Query<(&mut Transform, &mut Knockback)>,
+    tuning: Res<GameTuning>,
+    time: Res<GameTime>,
+) {
+    let decay = (1.0 - tuning.damage.knockback_decay * time.delta_seconds()).max(0.0);
+    for (mut transform, mut knockback) in units.iter_mut() {
+        if knockback.0 == Vec2::ZERO {
+            continue;
+        }
+        transform.translation += (knockback.0 * time.delta_seconds()).extend(0.0);
+        knockback.0 *= decay;
+        if knockback.0.length_squared() < 1e-6 {
+            knockback.0 = Vec2::ZERO;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::GameEventsPlugin;
+
+    fn hit(app: &mut App, target: Entity, count: usize) {
+        let mut requests = app.world.resource_mut::<Events<DamageRequest>>();
+        for _ in 0..count {
+            requests.send(DamageRequest {
+                source: target,
+                target,
+                team: Team(1),
+                amount: 0.4,
+                knockback: Vec2::ZERO,
+            });
+        }
+    }
+
+    #[test]
+    fn every_pellet_of_a_shot_lands_before_invulnerability() {
+        let mut app = App::new();
+        app.init_resource::<GameTime>()
+            .init_resource::<GameTuning>()
+            .add_plugin(GameEventsPlugin)
+            .add_plugin(DamagePlugin);
+        let target = app
+            .world
+            .spawn()
+            .insert(Health(10.0))
+            .insert(Team(0))
+            .insert(Invulnerable::default())
+            .id();
+
+        hit(&mut app, target, 5);
+        app.update();
+        let health = app.world.get::<Health>(target).unwrap().0;
+        assert!((health - 8.0).abs() < 1e-4, "health is {}", health);
+
+        // Game time doesn't move, so the target is still invulnerable from the first shot
+        hit(&mut app, target, 1);
+        app.update();
+        let health = app.world.get::<Health>(target).unwrap().0;
+        assert!((health - 8.0).abs() < 1e-4, "health is {}", health);
+    }
+}
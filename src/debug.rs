@@ -1,4 +1,4 @@
-use crate::prelude::*;
+use crate::{ai::EnemyBrain, prelude::*};
 use bevy_inspector_egui::RegisterInspectable;
 use std::time::Duration;
 
@@ -10,18 +10,27 @@ impl Plugin for DebugPlugin {
             app.add_system(slow_down);
         }
         app.register_type::<Health>()
+            .register_type::<MaxHealth>()
+            .register_type::<MeleeAttack>()
+            .register_type::<RangedAttack>()
+            .register_type::<Healer>()
+            .register_type::<CaptureCount>()
             .register_type::<RespawnTimer>()
             .register_type::<DamageFlash>()
+            .register_type::<Stunned>()
             .register_type::<Player>()
+            .register_type::<PlayerIntent>()
             .register_type::<Enemy>()
             .register_type::<Enemy>()
             .register_type::<Animation>()
             .register_type::<MovementStats>()
+            .register_type::<ObservedVelocity>()
             .register_type::<Bullet>()
             .register_type::<RectCollider>()
             .register_type::<CircleCollider>()
             .register_type::<Minion>()
-            .register_inspectable::<ChickenOrDog>()
+            .register_inspectable::<Team>()
+            .register_inspectable::<EnemyBrain>()
             .register_type::<Spawner>();
     }
 }
@@ -0,0 +1,155 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::prelude::*;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum DifficultyLevel {
+    Easy,
+    Normal,
+    Hard,
+    /// Uses `custom_difficulty` from the tuning file
+    Custom,
+}
+
+impl DifficultyLevel {
+    /// The level after this one, for cycling through them in the menu
+    pub fn next(self) -> Self {
+        match self {
+            DifficultyLevel::Easy => DifficultyLevel::Normal,
+            DifficultyLevel::Normal => DifficultyLevel::Hard,
+            DifficultyLevel::Hard => DifficultyLevel::Custom,
+            DifficultyLevel::Custom => DifficultyLevel::Easy,
+        }
+    }
+}
+
+impl fmt::Display for DifficultyLevel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            DifficultyLevel::Easy => "Easy",
+            DifficultyLevel::Normal => "Normal",
+            DifficultyLevel::Hard => "Hard",
+            DifficultyLevel::Custom => "Custom",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// How the teams run by the AI are scaled
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct DifficultyScaling {
+    /// Largest angle in radians an enemy hero's shot strays from its target
+    pub aim_noise: f32,
+    /// How much of its target's movement an enemy hero leads its shots by, from 0 to 1
+    pub aim_lead: f32,
+    /// Seconds between an enemy hero's decisions
+    pub reaction_time: f32,
+    /// How many times faster than normal the AI teams' spawners make minions
+    pub spawn_rate: f32,
+    /// Multiplies the health of the AI teams' units
+    pub health: f32,
+    /// Above 1 enemy heroes pick fights more often and retreat later
+    pub aggression: f32,
+}
+
+impl DifficultyScaling {
+    pub const EASY: Self = Self {
+        aim_noise: 0.35,
+        aim_lead: 0.0,
+        reaction_time: 0.6,
+        spawn_rate: 0.7,
+        health: 0.75,
+        aggression: 0.6,
+    };
+    pub const NORMAL: Self = Self {
+        aim_noise: 0.1,
+        aim_lead: 0.6,
+        reaction_time: 0.25,
+        spawn_rate: 1.0,
+        health: 1.0,
+        aggression: 1.0,
+    };
+    pub const HARD: Self = Self {
+        aim_noise: 0.0,
+        aim_lead: 1.0,
+        reaction_time: 0.05,
+        spawn_rate: 1.3,
+        health: 1.5,
+        aggression: 1.4,
+    };
+}
+
+impl Default for DifficultyScaling {
+    fn default() -> Self {
+        Self::NORMAL
+    }
+}
+
+/// The difficulty picked in the main menu
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Difficulty {
+    pub level: DifficultyLevel,
+    pub scaling: DifficultyScaling,
+}
+
+impl Difficulty {
+    pub fn new(level: DifficultyLevel, tuning: &GameTuning) -> Self {
+        let scaling = match level {
+            DifficultyLevel::Easy => DifficultyScaling::EASY,
+            DifficultyLevel::Normal => DifficultyScaling::NORMAL,
+            DifficultyLevel::Hard => DifficultyScaling::HARD,
+            DifficultyLevel::Custom => tuning.custom_difficulty,
+        };
+        Self { level, scaling }
+    }
+
+    /// Whether `team` is played by the AI rather than the player
+    pub fn scales(&self, team: Team) -> bool {
+        team != Team::PLAYER
+    }
+
+    /// The health a unit of `team` spawns with when its unit file gives it `hp`
+    pub fn health(&self, team: Team, hp: f32) -> f32 {
+        if self.scales(team) {
+            hp * self.scaling.health
+        } else {
+            hp
+        }
+    }
+
+    /// Seconds between minions from a spawner `team` owns
+    pub fn spawn_time(&self, team: Team, spawn_time: f32) -> f32 {
+        if self.scales(team) {
+            spawn_time / self.scaling.spawn_rate.max(f32::EPSILON)
+        } else {
+            spawn_time
+        }
+    }
+}
+
+impl Default for Difficulty {
+    fn default() -> Self {
+        Self {
+            level: DifficultyLevel::Normal,
+            scaling: DifficultyScaling::NORMAL,
+        }
+    }
+}
+
+pub struct DifficultyPlugin;
+
+impl Plugin for DifficultyPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Difficulty>()
+            .add_system(follow_custom_difficulty);
+    }
+}
+
+/// Keeps a custom difficulty in step with the tuning file as it's edited
+fn follow_custom_difficulty(tuning: Res<GameTuning>, mut difficulty: ResMut<Difficulty>) {
+    if tuning.is_changed() && difficulty.level == DifficultyLevel::Custom {
+        *difficulty = Difficulty::new(DifficultyLevel::Custom, &tuning);
+    }
+}
@@ -0,0 +1,297 @@
+use bevy::{
+    ecs::system::SystemParam,
+    utils::{Duration, HashMap},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{player::PlayerInputLabel, prelude::*, upgrade::buy_spawner_upgrades};
+
+/// What teams earn and what things cost, part of `GameTuning`
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct EconomyTuning {
+    /// Paid to the owner of each spawner every `income_interval` seconds
+    pub spawner_income: u32,
+    pub income_interval: f32,
+    /// Paid to the team that landed the killing blow
+    pub minion_bounty: u32,
+    pub hero_bounty: u32,
+    /// A hero's first upgrade costs this, each one after costs this much more than the last
+    pub hero_upgrade_cost: u32,
+    /// Added to the hero's max health and to its shots' damage by each upgrade
+    pub hero_upgrade_health: f32,
+    pub hero_upgrade_damage: f32,
+    /// A wave adds `wave_size` minions to every spawner the team owns
+    pub wave_cost: u32,
+    pub wave_size: u32,
+}
+
+impl Default for EconomyTuning {
+    fn default() -> Self {
+        Self {
+            spawner_income: 2,
+            income_interval: 1.0,
+            minion_bounty: 5,
+            hero_bounty: 25,
+            hero_upgrade_cost: 60,
+            hero_upgrade_health: 2.0,
+            hero_upgrade_damage: 0.25,
+            wave_cost: 50,
+            wave_size: 3,
+        }
+    }
+}
+
+/// How much each team has to spend
+#[derive(Default, Debug)]
+pub struct Funds(HashMap<Team, u32>);
+
+impl Funds {
+    pub fn get(&self, team: Team) -> u32 {
+        self.0.get(&team).copied().unwrap_or(0)
+    }
+}
+
+/// Where money came from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Income {
+    Spawner,
+    MinionKill,
+    HeroKill,
+}
+
+/// Something a team can spend money on
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Purchase {
+    /// The next tier for the owned spawner the hero is standing on
+    SpawnerUpgrade,
+    /// More health and damage for the hero
+    HeroUpgrade,
+    /// Extra minions from every owned spawner
+    MinionWave,
+}
+
+/// Sent whenever a team's funds change, for UI, audio and stats to react to
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EconomyEvent {
+    Earned {
+        team: Team,
+        amount: u32,
+        source: Income,
+    },
+    Spent {
+        team: Team,
+        amount: u32,
+        purchase: Purchase,
+    },
+}
+
+/// Changes `Funds` and sends the matching `EconomyEvent`, so no change goes unseen
+#[derive(SystemParam)]
+pub struct Bank<'w, 's> {
+    funds: ResMut<'w, Funds>,
+    events: EventWriter<'w, 's, EconomyEvent>,
+}
+
+impl<'w, 's> Bank<'w, 's> {
+    pub fn get(&self, team: Team) -> u32 {
+        self.funds.get(team)
+    }
+
+    pub fn earn(&mut self, team: Team, amount: u32, source: Income) {
+        *self.funds.0.entry(team).or_default() += amount;
+        self.events.send(EconomyEvent::Earned {
+            team,
+            amount,
+            source,
+        });
+    }
+
+    /// Takes `amount` from `team` if it can afford it
+    pub fn spend(&mut self, team: Team, amount: u32, purchase: Purchase) -> bool {
+        let funds = self.funds.0.entry(team).or_default();
+        if *funds < amount {
+            return false;
+        }
+        *funds -= amount;
+        self.events.send(EconomyEvent::Spent {
+            team,
+            amount,
+            purchase,
+        });
+        true
+    }
+}
+
+/// The team that last hurt a unit, which is paid the bounty when it dies
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub struct LastAttacker(pub Option<Team>);
+
+/// How many times a hero has been upgraded
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub struct HeroUpgrades(pub u32);
+
+struct IncomeTimer(Timer);
+
+pub struct EconomyPlugin;
+
+impl Plugin for EconomyPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Funds>()
+            .add_event::<EconomyEvent>()
+            .insert_resource(IncomeTimer(Timer::from_seconds(1.0, true)))
+            .add_system_set(SystemSet::on_enter(GameState::GamePlay).with_system(reset_funds))
+            .add_system_set(
+                SystemSet::on_update(GameState::GamePlay)
+                    .with_system(spawner_income)
+                    // Purchases draw on the same funds, so they always go in the same order
+                    .with_system(
+                        buy_hero_upgrades
+                            .after(PlayerInputLabel)
+                            .after(buy_spawner_upgrades),
+                    )
+                    .with_system(buy_minion_waves.after(buy_hero_upgrades)),
+            );
+    }
+}
+
+fn reset_funds(mut funds: ResMut<Funds>, mut timer: ResMut<IncomeTimer>) {
+    funds.0.clear();
+    timer.0.reset();
+}
+
+fn spawner_income(
+    spawners: Query<&Team, With<Spawner>>,
+    mut bank: Bank,
+    mut timer: ResMut<IncomeTimer>,
+    tuning: Res<GameTuning>,
+    time: Res<GameTime>,
+) {
+    let interval = Duration::from_secs_f32(tuning.economy.income_interval);
+    if timer.0.duration() != interval {
+        timer.0.set_duration(interval);
+    }
+    timer.0.tick(time.delta());
+    for _ in 0..timer.0.times_finished() {
+        for team in spawners.iter() {
+            bank.earn(*team, tuning.economy.spawner_income, Income::Spawner);
+        }
+    }
+}
+
+/// Players upgrade their hero when they ask to,
+/// AI heroes once every spawner their team owns is fully upgraded
+fn buy_hero_upgrades(
+    mut heroes: Query<
+        (
+            &mut HeroUpgrades,
+            &mut MaxHealth,
+            &mut Health,
+            &mut RangedAttack,
+            &Team,
+            Option<&PlayerIntent>,
+        ),
+        Or<(With<Player>, With<Enemy>)>,
+    >,
+    spawners: Query<(&Spawner, &Team)>,
+    mut bank: Bank,
+    difficulty: Res<Difficulty>,
+    tuning: Res<GameTuning>,
+) {
+    let economy = &tuning.economy;
+
+    for (mut upgrades, mut max_health, mut health, mut attack, team, intent) in heroes.iter_mut() {
+        let wanted = match intent {
+            Some(intent) => intent.purchase == Some(Purchase::HeroUpgrade),
+            None if difficulty.scales(*team) => {
+                let owned: Vec<&Spawner> = spawners
+                    .iter()
+                    .filter(|(_, owner)| *owner == team)
+                    .map(|(spawner, _)| spawner)
+                    .collect();
+                // A team without spawners saves up to upgrade the ones it takes
+                !owned.is_empty()
+                    && owned
+                        .iter()
+                        .all(|spawner| tuning.spawner_tiers.next_cost(spawner.level).is_none())
+            }
+            None => false,
+        };
+        if !wanted {
+            continue;
+        }
+
+        let cost = economy.hero_upgrade_cost * (upgrades.0 + 1);
+        if bank.spend(*team, cost, Purchase::HeroUpgrade) {
+            upgrades.0 += 1;
+            max_health.0 += economy.hero_upgrade_health;
+            health.0 += economy.hero_upgrade_health;
+            attack.damage += economy.hero_upgrade_damage;
+        }
+    }
+}
+
+/// Players call a wave when they ask to,
+/// AI teams when another team is taking one of their spawners
+fn buy_minion_waves(
+    players: Query<(&PlayerIntent, &Team), With<Player>>,
+    mut spawners: Query<(&mut Spawner, &Team)>,
+    mut bank: Bank,
+    difficulty: Res<Difficulty>,
+    tuning: Res<GameTuning>,
+) {
+    let mut buyers: Vec<Team> = players
+        .iter()
+        .filter(|(intent, _)| intent.purchase == Some(Purchase::MinionWave))
+        .map(|(_, team)| *team)
+        .collect();
+    buyers.extend(
+        spawners
+            .iter()
+            .filter(|(spawner, owner)| {
+                difficulty.scales(**owner) && spawner.queued == 0 && spawner.contested_by.is_some()
+            })
+            .map(|(_, owner)| *owner),
+    );
+    buyers.sort();
+    buyers.dedup();
+
+    for team in buyers {
+        if !bank.spend(team, tuning.economy.wave_cost, Purchase::MinionWave) {
+            continue;
+        }
+        for (mut spawner, owner) in spawners.iter_mut() {
+            if *owner == team {
+                spawner.queued += tuning.economy.wave_size;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spawner::{
+        spawner_capture_ai,
+        tests::{capture_app, spawner_under_attack},
+    };
+
+    #[test]
+    fn ai_calls_a_wave_when_its_spawner_is_being_taken() {
+        let owner = Team(1);
+        let tuning = GameTuning::default();
+
+        let mut app = capture_app();
+        app.insert_resource(Funds(
+            [(owner, tuning.economy.wave_cost)].into_iter().collect(),
+        ))
+        .init_resource::<Difficulty>()
+        .add_event::<EconomyEvent>()
+        .add_system(buy_minion_waves.after(spawner_capture_ai));
+        let spawner = spawner_under_attack(&mut app, owner, Team::PLAYER);
+        app.update();
+
+        let spawner = app.world.get::<Spawner>(spawner).unwrap();
+        assert_eq!(spawner.queued, tuning.economy.wave_size);
+        assert_eq!(app.world.resource::<Funds>().get(owner), 0);
+    }
+}
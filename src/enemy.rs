@@ -2,8 +2,15 @@ use heron::rapier_plugin::PhysicsWorld;
 use rand::Rng;
 
 use crate::{
-    assets::{BulletFrames, DogWalkFrames, Rotate},
+    ability::equip_abilities,
+    ai::{enemy_think, EnemyBrain, EnemyThinkLabel},
+    assets::{BulletFrames, UnitSprites},
+    economy::HeroUpgrades,
+    events::BulletFired,
+    navigation::{NavGrid, NavPath},
     prelude::*,
+    unit::{spawn_unit, UnitDef},
+    weapon::{equip_weapon, fire_weapon, Weapon},
 };
 
 pub struct EnemyPlugin;
@@ -12,7 +19,8 @@ impl Plugin for EnemyPlugin {
         app.add_system_set(SystemSet::on_enter(GameState::GamePlay).with_system(spawn_enemy))
             .add_system_set(
                 SystemSet::on_update(GameState::GamePlay)
-                    .with_system(enemy_ai)
+                    .with_system(enemy_think.label(EnemyThinkLabel))
+                    .with_system(enemy_ai.after(EnemyThinkLabel))
                     .with_system(enemy_shoot),
             );
     }
@@ -21,51 +29,76 @@ impl Plugin for EnemyPlugin {
 #[derive(Component)]
 struct BulletParentTag;
 
+/// Spawns a hero for every faction on the map other than the player's
 pub fn spawn_enemy(
     mut commands: Commands,
+    units: Res<Assets<UnitDef>>,
+    factions: Res<Factions>,
+    sprites: Option<Res<UnitSprites>>,
     map: Res<Assets<Map>>,
-    dog_walk: Res<DogWalkFrames>,
-    our_assets: Res<OurAssets>,
+    current_map: Res<CurrentMap>,
+    difficulty: Res<Difficulty>,
+    tuning: Res<GameTuning>,
 ) {
-    let map = map.get(our_assets.map.clone()).unwrap();
-    let size = 0.25;
-
-    commands
-        .spawn_bundle(SpriteSheetBundle {
-            sprite: dog_walk.frames[0].clone(),
-            texture_atlas: dog_walk.texture.clone(),
-            transform: Transform::from_translation(map.enemy_spawn.extend(800.0)),
-            ..default()
-        })
-        .insert(Animation {
-            current_frame: 0,
-            frames: dog_walk.frames.iter().map(|f| f.index).collect(),
-            alt_frames: Some(dog_walk.alt_frames.iter().map(|f| f.index).collect()),
-            playing_alt: false,
-            playing: true,
-            flip_x: true,
-            flip_y: false,
-            timer: Timer::from_seconds(2.0 / 10.0, true),
-        })
-        .insert(Enemy {
-            range: 2.5,
-            bullet_cooldown: Timer::from_seconds(0.6, true),
-        })
-        .insert(Health(PLAYER_HP))
-        .insert(DamageFlash {
-            timer: Timer::from_seconds(0.0, false),
-        })
-        .insert(MovementStats { speed: 0.2 })
-        .insert(RigidBody::Dynamic)
-        .insert(CollisionShape::Sphere { radius: size / 2.0 })
-        .insert(RotationConstraints::lock())
-        .insert(ChickenOrDog::Dog)
-        .insert(CollisionLayers::all_masks::<Layer>().with_group(Layer::Enemy))
-        .insert(RespawnTimer {
-            is_dead: false,
-            timer: Timer::from_seconds(0.0, false),
-        })
-        .insert(Name::new("Enemy"));
+    //create_map reports a missing map
+    let map = match map.get(current_map.0.clone()) {
+        Some(map) => map,
+        None => return,
+    };
+
+    for team in map.teams() {
+        if team == Team::PLAYER {
+            continue;
+        }
+        let faction = match factions.get(team) {
+            Some(faction) => faction,
+            None => {
+                warn!("Map has team {} but there is no faction for it", team.0);
+                continue;
+            }
+        };
+        let def = match units.get(&faction.hero) {
+            Some(def) => def,
+            None => {
+                error!("The hero of {} isn't loaded", faction.name);
+                continue;
+            }
+        };
+        let frames = sprites
+            .as_ref()
+            .and_then(|sprites| sprites.get(&faction.hero));
+
+        let enemy = spawn_unit(
+            &mut commands,
+            def,
+            frames,
+            team,
+            map.hero_spawn(team).extend(800.0),
+        );
+        let hp = difficulty.health(team, def.hp);
+        commands
+            .entity(enemy)
+            .insert(Health(hp))
+            .insert(MaxHealth(hp))
+            .insert(Enemy)
+            .insert(EnemyBrain::default())
+            .insert(HeroUpgrades::default())
+            .insert(NavPath::default())
+            .insert(RespawnTimer {
+                is_dead: false,
+                timer: Timer::from_seconds(0.0, false),
+            });
+        if let Some(name) = &def.weapon {
+            match tuning.weapons.get(name) {
+                Some(weapon) => equip_weapon(&mut commands, enemy, weapon),
+                None => error!(
+                    "{} has a weapon called {} that doesn't exist",
+                    def.name, name
+                ),
+            }
+        }
+        equip_abilities(&mut commands, enemy, def, &tuning.abilities);
+    }
 
     commands
         .spawn_bundle(TransformBundle::default())
@@ -73,176 +106,150 @@ pub fn spawn_enemy(
         .insert(Name::new("Enemy Bullets"));
 }
 
+/// Walks each hero toward whatever its `EnemyBrain` decided on
 fn enemy_ai(
-    mut minion_query: Query<
+    mut enemies: Query<
         (
             &GlobalTransform,
             &mut Transform,
             &MovementStats,
             &RespawnTimer,
+            &EnemyBrain,
+            &mut NavPath,
         ),
-        With<Enemy>,
-    >,
-    targets_query: Query<
-        (&GlobalTransform, Option<&ChickenOrDog>),
-        Or<(With<Spawner>, With<Player>, With<Enemy>)>,
+        (With<Enemy>, Without<Stunned>),
     >,
-    player_query: Query<&GlobalTransform, With<Player>>,
-    physics_world: PhysicsWorld,
-    time: Res<Time>,
+    nav_grid: Option<Res<NavGrid>>,
+    time: Res<GameTime>,
 ) {
-    for (global_transform, mut transform, movement_stats, respawn) in minion_query.iter_mut() {
-        if respawn.is_dead {
+    for (global_transform, mut transform, movement_stats, respawn, brain, mut nav_path) in
+        enemies.iter_mut()
+    {
+        if respawn.is_dead || brain.hold {
             continue;
         }
         let position = global_transform.translation.truncate();
 
-        let enemy_targets = targets_query
-            .iter()
-            .filter_map(|(transform, target_minion_type)| match target_minion_type {
-                None => Some(*transform),
-                Some(ChickenOrDog::Chicken) => Some(*transform),
-                _ => None,
-            })
-            .filter(|transform| {
-                physics_world
-                    .ray_cast_with_filter(
-                        position.extend(0.0),
-                        transform.translation - position.extend(0.0),
-                        false,
-                        CollisionLayers::none()
-                            .with_group(Layer::Wall)
-                            .with_mask(Layer::Wall),
-                        |_ent| true,
-                    )
-                    .is_none()
-            });
-
-        let target_position = {
-            if let Some(closest_target) = find_closest(position, enemy_targets) {
-                closest_target
-            } else {
-                player_query.single().translation.truncate()
-            }
-        };
-
-        let dir = target_position - position;
-        let dir = dir.try_normalize().unwrap_or_default().extend(0.0);
+        let dir = nav_path
+            .steer(
+                nav_grid.as_deref(),
+                position,
+                brain.target,
+                time.delta_seconds(),
+            )
+            .extend(0.0);
         transform.translation += dir * movement_stats.speed * time.delta_seconds();
     }
 }
 
 fn enemy_shoot(
     mut commands: Commands,
-    mut enemies: Query<(
-        &mut Enemy,
-        &GlobalTransform,
-        &Transform,
-        &mut Animation,
-        &RespawnTimer,
-    )>,
-    targets: Query<(&GlobalTransform, &ChickenOrDog), Or<(With<Player>, With<Minion>)>>,
+    mut enemies: Query<
+        (
+            &mut RangedAttack,
+            &Weapon,
+            &GlobalTransform,
+            &Transform,
+            &mut Animation,
+            &Team,
+            &RespawnTimer,
+        ),
+        (With<Enemy>, Without<Stunned>),
+    >,
+    targets: Query<
+        (&GlobalTransform, &Team, Option<&ObservedVelocity>),
+        Or<(With<Player>, With<Enemy>, With<Minion>)>,
+    >,
     physics_world: PhysicsWorld,
     parent: Query<Entity, With<BulletParentTag>>,
-    time: Res<Time>,
-    bullets: Res<BulletFrames>,
+    time: Res<GameTime>,
+    mut rng: ResMut<GameRng>,
+    mut fired: EventWriter<BulletFired>,
+    bullets: Option<Res<BulletFrames>>,
+    difficulty: Res<Difficulty>,
 ) {
     let parent = parent.single();
     let delta = time.delta();
 
-    for (mut enemy, global_transform, transform, mut animation, respawn) in enemies.iter_mut() {
+    for (mut attack, weapon, global_transform, transform, mut animation, team, respawn) in
+        enemies.iter_mut()
+    {
         if respawn.is_dead {
             animation.flip_y = true;
             continue;
         }
         animation.flip_y = false;
 
-        if !enemy.bullet_cooldown.finished() {
-            enemy.bullet_cooldown.tick(delta);
+        if !attack.cooldown.finished() {
+            attack.cooldown.tick(delta);
             continue;
         }
 
         let position = global_transform.translation.truncate();
 
-        let enemy_targets = targets
+        let target = targets
             .iter()
-            .filter_map(|(transform, team)| {
-                if *team == ChickenOrDog::Chicken {
-                    Some(transform)
-                } else {
-                    None
-                }
-            })
-            .filter(|transform| {
-                physics_world
-                    .ray_cast_with_filter(
-                        position.extend(0.0),
-                        transform.translation - position.extend(0.0),
-                        false,
-                        CollisionLayers::none()
-                            .with_group(Layer::Wall)
-                            .with_mask(Layer::Wall),
-                        |_ent| true,
-                    )
-                    .is_none()
+            .filter(|(_, target_team, _)| *target_team != team)
+            .map(|(transform, _, velocity)| {
+                let velocity = velocity.map(|observed| observed.velocity);
+                (
+                    transform.translation.truncate(),
+                    velocity.unwrap_or_default(),
+                )
             })
-            .cloned();
+            .filter(|(target, _)| has_line_of_sight(&physics_world, position, *target))
+            .min_by(|(a, _), (b, _)| {
+                a.distance(position)
+                    .partial_cmp(&b.distance(position))
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            });
 
-        enemy.bullet_cooldown.tick(time.delta());
+        attack.cooldown.tick(time.delta());
 
-        if let Some(target) = find_closest(position, enemy_targets) {
+        if let Some((target, velocity)) = target {
             let target_dist = Vec2::distance(target, position);
-            if target_dist > enemy.range {
-                return;
+            if target_dist > attack.range {
+                continue;
             }
 
-            let target_dir = (target - position).normalize();
+            // Aim where the target will be when the bullet gets there,
+            // and hold fire if a wall is in the way of that shot
+            let lead = velocity * difficulty.scaling.aim_lead;
+            let aim = lead_target(position, target, lead, attack.bullet_speed).unwrap_or(target);
+            if !has_line_of_sight(&physics_world, position, aim) {
+                continue;
+            }
+
+            // Tuning is hand edited, a negative noise would be an empty range
+            let noise = difficulty.scaling.aim_noise.abs();
+            let target_dir = Mat2::from_angle(rng.gen_range(-noise..=noise))
+                * (aim - position).try_normalize().unwrap_or(Vec2::X);
 
             let mut transform = *transform;
             transform.translation.z += 1.0;
 
+            animation.flip_x = (target_dir.x < 0.0) == animation.faces_right;
             if target_dir.x < 0.0 {
-                animation.flip_x = true;
                 transform.translation.x -= 0.08;
             } else {
-                animation.flip_x = false;
                 transform.translation.x += 0.08;
             }
 
-            let size = 0.1;
-
-            let num = rand::thread_rng().gen_range(0..2);
-            let sprite = bullets.frames[num + 2].clone();
-            //sprite.custom_size = Some(Vec2::splat(size));
             animation.current_frame = 0;
             animation.playing_alt = true;
 
-            let bullet = commands
-                .spawn_bundle(SpriteSheetBundle {
-                    sprite,
-                    texture_atlas: bullets.texture.clone(),
-                    transform,
-                    ..default()
-                })
-                .insert(Bullet {
-                    speed: 0.2,
-                    direction: target_dir,
-                })
-                .insert(Rotate)
-                .insert(ChickenOrDog::Dog)
-                .insert(RigidBody::Sensor)
-                .insert(CollisionShape::Sphere { radius: size / 2.0 })
-                .insert(RotationConstraints::lock())
-                .insert(
-                    CollisionLayers::all_masks::<Layer>()
-                        .with_group(Layer::Bullet)
-                        .without_mask(Layer::Bullet)
-                        .without_mask(Layer::Enemy),
-                )
-                .insert(Collisions::default())
-                .insert(Name::new("Enemy Bullet"))
-                .id();
-            commands.entity(parent).add_child(bullet);
+            let shot = fire_weapon(
+                &mut commands,
+                &mut fired,
+                &mut rng,
+                transform,
+                *team,
+                &weapon.0,
+                &attack,
+                target_dir,
+                bullets.as_deref(),
+            );
+            commands.entity(parent).push_children(&shot);
         }
     }
 }
@@ -0,0 +1,70 @@
+//! What happens in a match, sent by the combat and capture systems
+//! so audio, particles, UI and stats can react without touching them
+
+use crate::{prelude::*, rules::MatchResult};
+
+/// Health taken from `target` by `source`, the bullet or minion that hit it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DamageDealt {
+    pub source: Entity,
+    pub target: Entity,
+    pub amount: f32,
+}
+
+/// A minion died or a hero went down, `killer` is the team that landed the last hit
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UnitDied {
+    pub unit: Entity,
+    pub team: Team,
+    pub killer: Option<Team>,
+    pub hero: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HeroRespawned {
+    pub hero: Entity,
+    pub team: Team,
+    pub position: Vec2,
+}
+
+/// A spawner was captured, `None` is neutral
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpawnerOwnerChanged {
+    pub spawner: Entity,
+    pub previous: Option<Team>,
+    pub owner: Option<Team>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BulletFired {
+    pub bullet: Entity,
+    pub team: Team,
+    pub position: Vec2,
+    pub direction: Vec2,
+}
+
+/// A hero used the ability in `slot` of its `HeroAbilities`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AbilityUsed {
+    pub hero: Entity,
+    pub team: Team,
+    pub slot: usize,
+    pub position: Vec2,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MatchEnded(pub MatchResult);
+
+pub struct GameEventsPlugin;
+
+impl Plugin for GameEventsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<DamageDealt>()
+            .add_event::<UnitDied>()
+            .add_event::<HeroRespawned>()
+            .add_event::<SpawnerOwnerChanged>()
+            .add_event::<BulletFired>()
+            .add_event::<AbilityUsed>()
+            .add_event::<MatchEnded>();
+    }
+}
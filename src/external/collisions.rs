@@ -42,6 +42,25 @@ impl Collisions {
     pub fn collision_data(&self) -> impl Iterator<Item = &CollisionData> + '_ {
         self.0.values()
     }
+
+    /// Collisions with `entities`, for tests that run without physics
+    #[cfg(test)]
+    pub(crate) fn with_entities(entities: impl IntoIterator<Item = Entity>) -> Self {
+        Self(
+            entities
+                .into_iter()
+                .map(|entity| {
+                    let data = CollisionData::new(
+                        entity,
+                        entity,
+                        heron::CollisionLayers::default(),
+                        Vec::new(),
+                    );
+                    (entity, data)
+                })
+                .collect(),
+        )
+    }
 }
 
 /// Adds entity to CollidingEntities on starting collision and removes from it when the
@@ -0,0 +1,197 @@
+use std::{fs, path::PathBuf};
+
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    reflect::TypeUuid,
+    utils::{BoxedFuture, HashMap},
+};
+use rand::Rng;
+use serde::Deserialize;
+
+use crate::{
+    prelude::*,
+    unit::{read_unit, UnitDef},
+};
+
+/// One side of a match
+#[derive(Debug, Clone)]
+pub struct Faction {
+    pub name: String,
+    pub color: Color,
+    pub hero: Handle<UnitDef>,
+    /// The minions the faction's spawners choose between, each with its weight
+    pub minions: Vec<(Handle<UnitDef>, u32)>,
+    /// Building frames for (owned, being captured),
+    /// factions without their own art get the neutral building in their color
+    pub building_frames: Option<(usize, usize)>,
+}
+
+#[derive(Deserialize)]
+struct FactionDesc {
+    name: String,
+    color: (f32, f32, f32),
+    hero: String,
+    /// (unit file, weight) of each minion
+    minions: Vec<(String, u32)>,
+    #[serde(default)]
+    building_frames: Option<(usize, usize)>,
+}
+
+/// Every faction that can take part in a match, indexed by `Team`
+#[derive(Debug, Clone, Default, TypeUuid)]
+#[uuid = "8d3c0f4a-58b1-4e0e-9f3b-1a7c2e6d4b90"]
+pub struct Factions(pub Vec<Faction>);
+
+impl Faction {
+    /// Picks a minion from the weighted table, `None` if nothing has any weight
+    pub fn pick_minion(&self, rng: &mut GameRng) -> Option<&Handle<UnitDef>> {
+        let total: u32 = self.minions.iter().map(|(_, weight)| weight).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut roll = rng.gen_range(0..total);
+        for (minion, weight) in &self.minions {
+            if roll < *weight {
+                return Some(minion);
+            }
+            roll -= weight;
+        }
+        None
+    }
+}
+
+impl Factions {
+    pub fn get(&self, team: Team) -> Option<&Faction> {
+        self.0.get(team.0 as usize)
+    }
+
+    pub fn teams(&self) -> impl Iterator<Item = Team> {
+        (0..self.0.len() as u8).map(Team)
+    }
+
+    pub fn color(&self, team: Team) -> Color {
+        self.get(team)
+            .map(|faction| faction.color)
+            .unwrap_or(Color::GRAY)
+    }
+
+    /// Reads the faction file and its units straight from `assets_dir` without resolving their art,
+    /// for apps that run without the asset server load phase
+    pub fn load_from_dir(
+        assets_dir: &str,
+        path: &str,
+        units: &mut Assets<UnitDef>,
+    ) -> anyhow::Result<Self> {
+        let read = |path: &str| {
+            let path = PathBuf::from(assets_dir).join(path);
+            fs::read_to_string(&path).with_context(|| format!("failed to read {}", path.display()))
+        };
+
+        let descs = ron::de::from_str::<Vec<FactionDesc>>(&read(path)?)
+            .with_context(|| format!("failed to parse {}", path))?;
+
+        let mut loaded: HashMap<String, Handle<UnitDef>> = HashMap::default();
+        let mut load = |path: &str| -> anyhow::Result<Handle<UnitDef>> {
+            if let Some(handle) = loaded.get(path) {
+                return Ok(handle.clone());
+            }
+            let def = ron::de::from_str::<UnitDef>(&read(path)?)
+                .with_context(|| format!("failed to parse {}", path))?;
+            let handle = units.add(def);
+            loaded.insert(path.to_string(), handle.clone());
+            Ok(handle)
+        };
+
+        let factions = descs
+            .into_iter()
+            .map(|desc| {
+                Ok(Faction {
+                    hero: load(&desc.hero)?,
+                    minions: desc
+                        .minions
+                        .iter()
+                        .map(|(path, weight)| Ok((load(path)?, *weight)))
+                        .collect::<anyhow::Result<_>>()?,
+                    name: desc.name,
+                    color: Color::rgb(desc.color.0, desc.color.1, desc.color.2),
+                    building_frames: desc.building_frames,
+                })
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self(factions))
+    }
+}
+
+pub struct FactionPlugin;
+
+impl Plugin for FactionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<Factions>()
+            .init_asset_loader::<FactionsLoader>()
+            .add_system_set(SystemSet::on_exit(GameState::Splash).with_system(select_factions));
+    }
+}
+
+fn select_factions(
+    our_assets: Res<OurAssets>,
+    factions: Res<Assets<Factions>>,
+    mut commands: Commands,
+) {
+    match factions.get(our_assets.factions.clone()) {
+        Some(factions) => commands.insert_resource(factions.clone()),
+        None => error!("The faction list failed to load"),
+    }
+}
+
+/// Loads a `.factions` file, with every unit it names as a labeled sub asset
+/// so they are ready as soon as the faction list is
+#[derive(Default)]
+pub struct FactionsLoader;
+
+impl AssetLoader for FactionsLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let descs = ron::de::from_bytes::<Vec<FactionDesc>>(bytes)?;
+
+            let mut units: HashMap<String, Handle<UnitDef>> = HashMap::default();
+            let mut factions = Vec::new();
+            for desc in descs {
+                let paths =
+                    std::iter::once(&desc.hero).chain(desc.minions.iter().map(|(path, _)| path));
+                for path in paths {
+                    if !units.contains_key(path) {
+                        let (def, sprite_sheet) = read_unit(load_context, path).await?;
+                        let handle = load_context.set_labeled_asset(
+                            path,
+                            LoadedAsset::new(def).with_dependency(sprite_sheet),
+                        );
+                        units.insert(path.clone(), handle);
+                    }
+                }
+
+                factions.push(Faction {
+                    hero: units[&desc.hero].clone(),
+                    minions: desc
+                        .minions
+                        .iter()
+                        .map(|(path, weight)| (units[path].clone(), *weight))
+                        .collect(),
+                    name: desc.name,
+                    color: Color::rgb(desc.color.0, desc.color.1, desc.color.2),
+                    building_frames: desc.building_frames,
+                });
+            }
+
+            load_context.set_default_asset(LoadedAsset::new(Factions(factions)));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["factions"]
+    }
+}
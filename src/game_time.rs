@@ -0,0 +1,58 @@
+use bevy::{prelude::*, utils::Duration};
+
+/// Frame delta used by all gameplay systems.
+/// Mirrors bevy's `Time` unless a fixed step is set, which is how headless matches stay deterministic
+#[derive(Default, Debug)]
+pub struct GameTime {
+    delta: Duration,
+    elapsed: Duration,
+    pub fixed_step: Option<Duration>,
+    /// Stops time while the game is paused
+    pub paused: bool,
+}
+
+impl GameTime {
+    pub fn fixed(step: Duration) -> Self {
+        Self {
+            fixed_step: Some(step),
+            ..default()
+        }
+    }
+
+    pub fn delta(&self) -> Duration {
+        self.delta
+    }
+
+    pub fn delta_seconds(&self) -> f32 {
+        self.delta.as_secs_f32()
+    }
+
+    /// Total simulated time, only advanced by `delta`
+    pub fn elapsed_seconds(&self) -> f32 {
+        self.elapsed.as_secs_f32()
+    }
+}
+
+/// Systems that change the step run before this, systems that read the new delta early run after it
+#[derive(SystemLabel, Clone, Hash, Debug, PartialEq, Eq)]
+pub struct UpdateGameTimeLabel;
+
+pub struct GameTimePlugin;
+
+impl Plugin for GameTimePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GameTime>().add_system_to_stage(
+            CoreStage::First,
+            update_game_time.label(UpdateGameTimeLabel),
+        );
+    }
+}
+
+fn update_game_time(mut game_time: ResMut<GameTime>, time: Res<Time>) {
+    game_time.delta = if game_time.paused {
+        Duration::ZERO
+    } else {
+        game_time.fixed_step.unwrap_or_else(|| time.delta())
+    };
+    game_time.elapsed += game_time.delta;
+}
@@ -1,4 +1,4 @@
-use crate::prelude::*;
+use crate::{prelude::*, rules::entered_game_over};
 use bevy::{render::camera::Camera2d, utils::Duration};
 use bevy_tweening::{
     lens::{TransformPositionLens, TransformScaleLens},
@@ -10,10 +10,9 @@ pub struct GameOverPlugin;
 impl Plugin for GameOverPlugin {
     fn build(&self, app: &mut App) {
         app.add_system_set(
-            SystemSet::on_enter(GameState::GameOver { won: true }).with_system(camera_animation),
-        );
-        app.add_system_set(
-            SystemSet::on_enter(GameState::GameOver { won: false }).with_system(camera_animation),
+            SystemSet::new()
+                .with_run_criteria(entered_game_over)
+                .with_system(camera_animation),
         );
     }
 }
@@ -0,0 +1,102 @@
+use bevy::{
+    asset::AssetPlugin, hierarchy::HierarchyPlugin, input::InputPlugin, transform::TransformPlugin,
+    utils::Duration,
+};
+use heron::PhysicsSteps;
+
+use crate::{
+    ability::AbilityPlugin,
+    bot::BotPlugin,
+    bullet::BulletPlugin,
+    damage::DamagePlugin,
+    difficulty::DifficultyPlugin,
+    economy::EconomyPlugin,
+    enemy::EnemyPlugin,
+    events::GameEventsPlugin,
+    external::ExternalPlugin,
+    faction::FactionPlugin,
+    game_time::GameTimePlugin,
+    map::{ensure_playable, MapPlugin},
+    minion::MinionPlugin,
+    navigation::NavigationPlugin,
+    player::PlayerPlugin,
+    prelude::*,
+    rules::{MatchResult, RulesPlugin},
+    spawner::SpawnerPlugin,
+    squad::SquadPlugin,
+    tuning::TuningPlugin,
+    unit::{UnitDef, UnitPlugin},
+    upgrade::UpgradePlugin,
+};
+
+/// 10 minutes of game time at 60 steps per second, long enough for any bot match to end
+pub const MAX_STEPS: usize = 60 * 60 * 10;
+
+/// Builds an app that plays a full match on `map` without a window, GPU or any loaded art.
+/// Factions and their units are read from `assets/main.factions` and balance from `assets/main.tuning`
+/// Nothing drives the chicken: add `take_over_player` for a bot match or a `ReplayPlugin` playback
+/// Every frame advances gameplay and physics by exactly `timestep`, so drive it with [`run_match`]
+/// rather than `App::run`
+pub fn build_headless_app(map: Map, timestep: Duration) -> anyhow::Result<App> {
+    ensure_playable(&map)?;
+
+    let mut app = App::new();
+
+    app.add_state(GameState::GamePlay)
+        .insert_resource(GameTime::fixed(timestep))
+        .insert_resource(PhysicsSteps::every_frame(timestep))
+        .add_plugins(MinimalPlugins)
+        .add_plugin(TransformPlugin)
+        .add_plugin(HierarchyPlugin)
+        .add_plugin(InputPlugin)
+        .add_plugin(AssetPlugin)
+        .add_plugin(PhysicsPlugin::default())
+        .add_plugin(GameTimePlugin)
+        .add_plugin(GameEventsPlugin)
+        .add_plugin(ExternalPlugin)
+        .add_plugin(MapPlugin)
+        .add_plugin(PlayerPlugin)
+        .add_plugin(EnemyPlugin)
+        .add_plugin(BulletPlugin)
+        .add_plugin(DamagePlugin)
+        .add_plugin(AbilityPlugin)
+        .add_plugin(MinionPlugin)
+        .add_plugin(NavigationPlugin)
+        .add_plugin(SpawnerPlugin)
+        .add_plugin(RulesPlugin)
+        .add_plugin(SquadPlugin)
+        .add_plugin(EconomyPlugin)
+        .add_plugin(UpgradePlugin)
+        .add_plugin(BotPlugin)
+        .add_plugin(UnitPlugin)
+        .add_plugin(FactionPlugin)
+        .add_plugin(TuningPlugin)
+        .add_plugin(DifficultyPlugin)
+        .init_resource::<GameRng>();
+
+    let map = app.world.resource_mut::<Assets<Map>>().add(map);
+    app.insert_resource(CurrentMap(map));
+
+    let factions = Factions::load_from_dir(
+        "assets",
+        "main.factions",
+        &mut app.world.resource_mut::<Assets<UnitDef>>(),
+    )?;
+    app.insert_resource(factions);
+    app.insert_resource(GameTuning::load_from_dir("assets", "main.tuning")?);
+
+    Ok(app)
+}
+
+/// Steps the app until one of the map's win conditions ends the match and returns how it ended.
+/// Gives up with `None` after `max_steps` frames so a stalled match can't hang CI
+pub fn run_match(app: &mut App, max_steps: usize) -> Option<MatchResult> {
+    for _ in 0..max_steps {
+        app.update();
+
+        if let GameState::GameOver(result) = app.world.resource::<State<GameState>>().current() {
+            return Some(*result);
+        }
+    }
+    None
+}
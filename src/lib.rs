@@ -3,20 +3,41 @@
 
 pub const SCALE: f32 = 2.0 / 3.0;
 
+pub mod ability;
+pub mod ai;
 pub mod assets;
 pub mod audio;
+pub mod bot;
 pub mod bullet;
+pub mod damage;
 pub mod debug;
+pub mod difficulty;
+pub mod economy;
 pub mod enemy;
+pub mod events;
 pub mod external;
+pub mod faction;
+pub mod game_time;
 pub mod gameover;
+pub mod headless;
 pub mod map;
 pub mod menus;
 pub mod minion;
+pub mod navigation;
 pub mod particles;
+pub mod pause;
 pub mod player;
 pub mod prelude;
+pub mod replay;
+pub mod rng;
+pub mod rules;
 pub mod spawner;
+pub mod squad;
+pub mod targeting;
+pub mod tuning;
+pub mod unit;
+pub mod upgrade;
+pub mod weapon;
 pub mod world_ui;
 
 #[derive(Eq, PartialEq, Hash, Clone, Debug)]
@@ -25,5 +46,7 @@ pub enum GameState {
     MainMenu,
     Tutorial,
     GamePlay,
-    GameOver { won: bool },
+    /// Pushed over `GamePlay`, which stays underneath until the player resumes
+    Paused,
+    GameOver(rules::MatchResult),
 }
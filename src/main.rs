@@ -11,13 +11,53 @@ pub const HEIGHT: f32 = 900.0 * SCALE;
 pub const RESOLUTION: f32 = 16.0 / 9.0;
 
 use rust_gamejam::{
-    assets::GameAssetsPlugin, audio::GameAudioPlugin, bullet::BulletPlugin, debug::DebugPlugin,
-    enemy::EnemyPlugin, external::ExternalPlugin, gameover::GameOverPlugin, map::MapPlugin,
-    menus::MenuPlugin, minion::*, particles::ParticlePlugin, player::PlayerPlugin, prelude::*,
-    spawner::SpawnerPlugin, world_ui::BarMaterialPlugin, SCALE,
+    ability::AbilityPlugin,
+    assets::GameAssetsPlugin,
+    audio::GameAudioPlugin,
+    bot::BotPlugin,
+    bullet::BulletPlugin,
+    damage::DamagePlugin,
+    debug::DebugPlugin,
+    difficulty::DifficultyPlugin,
+    economy::EconomyPlugin,
+    enemy::EnemyPlugin,
+    events::GameEventsPlugin,
+    external::ExternalPlugin,
+    faction::FactionPlugin,
+    game_time::GameTimePlugin,
+    gameover::GameOverPlugin,
+    map::{MapPath, MapPlugin},
+    menus::MenuPlugin,
+    minion::*,
+    navigation::NavigationPlugin,
+    particles::ParticlePlugin,
+    pause::PausePlugin,
+    player::PlayerPlugin,
+    prelude::*,
+    replay::ReplayPlugin,
+    rules::RulesPlugin,
+    spawner::SpawnerPlugin,
+    squad::SquadPlugin,
+    tuning::TuningPlugin,
+    unit::UnitPlugin,
+    upgrade::UpgradePlugin,
+    world_ui::BarMaterialPlugin,
+    SCALE,
 };
 
+/// Usage: `game [map] [--record <replay>]`, where `map` is relative to the assets folder
 fn main() {
+    let mut args = std::env::args().skip(1);
+    let mut map_path = MapPath::default();
+    let mut record_path = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--record" => record_path = args.next(),
+            _ => map_path = MapPath(arg),
+        }
+    }
+    let replay_map = format!("assets/{}", map_path.0);
+
     let mut app = App::new();
 
     AssetLoader::new(GameState::Splash)
@@ -30,6 +70,7 @@ fn main() {
             watch_for_changes: true,
             ..default()
         })
+        .insert_resource(map_path)
         .insert_resource(ClearColor(Color::hex(&"24523b").unwrap()))
         .insert_resource(WindowDescriptor {
             width: HEIGHT * RESOLUTION,
@@ -45,27 +86,52 @@ fn main() {
             ..Default::default()
         })
         .add_plugin(PhysicsPlugin::default())
+        .add_plugin(GameTimePlugin)
+        .add_plugin(GameEventsPlugin)
+        .init_resource::<GameRng>()
         .add_plugin(PlayerPlugin)
+        .add_plugin(BotPlugin)
         .add_plugin(EnemyPlugin)
         .add_plugin(BulletPlugin)
+        .add_plugin(DamagePlugin)
+        .add_plugin(AbilityPlugin)
         .add_plugin(MapPlugin)
         .add_plugin(GameAssetsPlugin)
         .add_plugin(MinionPlugin)
+        .add_plugin(NavigationPlugin)
+        .add_plugin(UnitPlugin)
+        .add_plugin(FactionPlugin)
+        .add_plugin(TuningPlugin)
+        .add_plugin(DifficultyPlugin)
         .add_plugin(WorldInspectorPlugin::new())
         .add_plugin(ExternalPlugin)
         .add_plugin(ParticlePlugin)
         .add_plugin(SpawnerPlugin)
+        .add_plugin(RulesPlugin)
+        .add_plugin(SquadPlugin)
+        .add_plugin(EconomyPlugin)
+        .add_plugin(UpgradePlugin)
         .add_plugin(BarMaterialPlugin)
         .add_plugin(GameAudioPlugin)
         .add_plugin(DebugPlugin)
         .add_plugin(MenuPlugin)
         .add_plugin(TweeningPlugin)
         .add_plugin(GameOverPlugin)
+        .add_plugin(PausePlugin)
         .add_startup_system(spawn_camera)
         .add_system_set(SystemSet::on_enter(GameState::GamePlay).with_system(spawn_background))
         .add_system(toggle_inspector)
-        .register_type::<Animation>()
-        .run();
+        .register_type::<Animation>();
+
+    if let Some(path) = record_path {
+        app.add_plugin(ReplayPlugin::Record {
+            path,
+            map: replay_map,
+            timestep: None,
+        });
+    }
+
+    app.run();
 }
 
 fn toggle_inspector(
@@ -1,4 +1,4 @@
-use crate::prelude::*;
+use crate::{prelude::*, rules::MatchRules};
 
 use bevy::{
     asset::{AssetLoader, LoadContext, LoadedAsset},
@@ -7,20 +7,199 @@ use bevy::{
 };
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Deserialize, Serialize)]
+mod migration;
+mod validation;
+
+pub use validation::{ensure_playable, validate, MapIssue, SpawnPoint};
+
+/// Format version `Map` is written in.
+/// Bump it and add an upgrade step to `migration` whenever the format changes
+pub const MAP_VERSION: u32 = 2;
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
 pub struct Rect {
     pub position: Vec2,
     pub size: Vec2,
     pub rotation: f32,
 }
 
+impl Rect {
+    pub fn corners(&self) -> [Vec2; 4] {
+        let rotation = Mat2::from_angle(self.rotation);
+        let half = self.size / 2.0;
+        [
+            Vec2::new(-half.x, -half.y),
+            Vec2::new(half.x, -half.y),
+            Vec2::new(half.x, half.y),
+            Vec2::new(-half.x, half.y),
+        ]
+        .map(|corner| self.position + rotation * corner)
+    }
+
+    pub fn contains(&self, point: Vec2) -> bool {
+        let local = Mat2::from_angle(-self.rotation) * (point - self.position);
+        local.abs().cmple(self.size / 2.0).all()
+    }
+
+    /// Outward normal of the side closest to `point`
+    pub fn normal_at(&self, point: Vec2) -> Vec2 {
+        let local = Mat2::from_angle(-self.rotation) * (point - self.position);
+        let gap = self.size / 2.0 - local.abs();
+        let normal = if gap.x <= gap.y {
+            Vec2::new(local.x.signum(), 0.0)
+        } else {
+            Vec2::new(0.0, local.y.signum())
+        };
+        Mat2::from_angle(self.rotation) * normal
+    }
+
+    /// Whether the two rects share any area, walls that only touch don't overlap
+    pub fn overlaps(&self, other: &Rect) -> bool {
+        let (ours, theirs) = (self.corners(), other.corners());
+        let axes = [self.rotation, other.rotation]
+            .into_iter()
+            .flat_map(|rotation| {
+                [
+                    Vec2::new(rotation.cos(), rotation.sin()),
+                    Vec2::new(-rotation.sin(), rotation.cos()),
+                ]
+            });
+
+        let project = |corners: &[Vec2; 4], axis: Vec2| {
+            corners
+                .iter()
+                .map(|corner| corner.dot(axis))
+                .fold((f32::MAX, f32::MIN), |(min, max), d| {
+                    (min.min(d), max.max(d))
+                })
+        };
+        for axis in axes {
+            let (our_min, our_max) = project(&ours, axis);
+            let (their_min, their_max) = project(&theirs, axis);
+            if our_max <= their_min + 1e-4 || their_max <= our_min + 1e-4 {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// A wall spawned from one of the map's rects
+#[derive(Component, Debug, Clone, Copy)]
+pub struct Wall(pub Rect);
+
+/// The playable area of a map
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize)]
+pub struct MapBounds {
+    pub min: Vec2,
+    pub max: Vec2,
+}
+
+impl MapBounds {
+    /// The smallest bounds that hold every wall
+    pub fn around(rects: &[Rect]) -> Self {
+        let mut corners = rects.iter().flat_map(Rect::corners);
+        let first = match corners.next() {
+            Some(corner) => corner,
+            None => return Self::default(),
+        };
+        corners.fold(
+            Self {
+                min: first,
+                max: first,
+            },
+            |bounds, corner| Self {
+                min: bounds.min.min(corner),
+                max: bounds.max.max(corner),
+            },
+        )
+    }
+
+    pub fn contains(&self, point: Vec2) -> bool {
+        point.cmpge(self.min).all() && point.cmple(self.max).all()
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize, TypeUuid)]
 #[uuid = "615963e9-3a3d-4eaa-bed3-76e8f05a1070"]
 pub struct Map {
+    /// Always `MAP_VERSION` once loaded, older files are upgraded by the loader
+    pub version: u32,
+    pub name: String,
+    #[serde(default)]
+    pub author: String,
+    pub bounds: MapBounds,
     pub rects: Vec<Rect>,
-    pub spawn_locations: Vec<(Vec2, ChickenOrDog)>,
+    pub spawn_locations: Vec<(Vec2, Team)>,
     pub player_spawn: Vec2,
     pub enemy_spawn: Vec2,
+    /// Hero spawns of any teams after the first two
+    #[serde(default)]
+    pub hero_spawns: Vec<(Vec2, Team)>,
+    /// How matches on this map are won
+    #[serde(default)]
+    pub rules: MatchRules,
+}
+
+impl Map {
+    /// An empty map in the current format
+    pub fn new(name: &str) -> Self {
+        Self {
+            version: MAP_VERSION,
+            name: name.to_string(),
+            author: String::new(),
+            bounds: MapBounds::default(),
+            rects: Vec::new(),
+            spawn_locations: Vec::new(),
+            player_spawn: Vec2::ZERO,
+            enemy_spawn: Vec2::ZERO,
+            hero_spawns: Vec::new(),
+            rules: MatchRules::default(),
+        }
+    }
+
+    /// Parses a `.map` file of any version, upgrading it to the current format.
+    /// `name` is used for maps saved before they had one
+    pub fn parse(bytes: &[u8], name: &str) -> anyhow::Result<Self> {
+        migration::upgrade(bytes, name)
+    }
+
+    /// Every team with a hero or spawner on this map
+    pub fn teams(&self) -> Vec<Team> {
+        let mut teams = vec![Team(0), Team(1)];
+        teams.extend(self.spawn_locations.iter().map(|(_, team)| *team));
+        teams.extend(self.hero_spawns.iter().map(|(_, team)| *team));
+        teams.sort();
+        teams.dedup();
+        teams
+    }
+
+    /// Where `team`'s hero starts, falling back to one of its spawners
+    pub fn hero_spawn(&self, team: Team) -> Vec2 {
+        match team {
+            Team(0) => self.player_spawn,
+            Team(1) => self.enemy_spawn,
+            _ => self
+                .hero_spawns
+                .iter()
+                .chain(self.spawn_locations.iter())
+                .find(|(_, spawn_team)| *spawn_team == team)
+                .map(|(location, _)| *location)
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// The map the current match is played on
+pub struct CurrentMap(pub Handle<Map>);
+
+/// Asset path of the map to load, set from the command line
+pub struct MapPath(pub String);
+
+impl Default for MapPath {
+    fn default() -> Self {
+        Self("main.map".to_string())
+    }
 }
 
 pub struct MapPlugin;
@@ -29,12 +208,40 @@ impl Plugin for MapPlugin {
     fn build(&self, app: &mut App) {
         app.add_asset::<Map>()
             .init_asset_loader::<MapLoader>()
-            .add_system_set(SystemSet::on_exit(GameState::Splash).with_system(create_map));
+            .init_resource::<MapPath>()
+            .add_startup_system(select_map)
+            .add_system_set(SystemSet::on_enter(GameState::GamePlay).with_system(create_map));
+    }
+}
+
+/// Starts loading the map from `MapPath`, unless the app already picked one
+fn select_map(
+    map_path: Res<MapPath>,
+    current_map: Option<Res<CurrentMap>>,
+    asset_server: Res<AssetServer>,
+    mut commands: Commands,
+) {
+    if current_map.is_none() {
+        commands.insert_resource(CurrentMap(asset_server.load(map_path.0.as_str())));
     }
 }
 
-fn create_map(map_assets: Res<Assets<Map>>, our_assets: Res<OurAssets>, mut commands: Commands) {
-    let map = map_assets.get(our_assets.map.clone()).unwrap();
+fn create_map(
+    map_assets: Res<Assets<Map>>,
+    current_map: Res<CurrentMap>,
+    mut state: ResMut<State<GameState>>,
+    mut commands: Commands,
+) {
+    let map = match map_assets.get(current_map.0.clone()) {
+        Some(map) => map,
+        None => {
+            error!("The map failed to load or isn't playable, returning to the main menu");
+            if let Err(e) = state.set(GameState::MainMenu) {
+                error!("{:?}", e);
+            }
+            return;
+        }
+    };
     let mut walls = Vec::new();
     for rect in &map.rects {
         walls.push(
@@ -61,6 +268,7 @@ fn create_map(map_assets: Res<Assets<Map>>, our_assets: Res<OurAssets>, mut comm
                 })
                 .insert(RotationConstraints::lock())
                 .insert(CollisionLayers::all_masks::<Layer>().with_group(Layer::Wall))
+                .insert(Wall(*rect))
                 .insert(Name::new("Wall"))
                 .id(),
         );
@@ -84,8 +292,15 @@ impl AssetLoader for MapLoader {
         load_context: &'a mut LoadContext,
     ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
         Box::pin(async move {
-            let custom_asset = ron::de::from_bytes::<Map>(bytes)?;
-            load_context.set_default_asset(LoadedAsset::new(custom_asset));
+            let path = load_context.path();
+            let name = path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let map = Map::parse(bytes, &name)
+                .with_context(|| format!("failed to load map {}", path.display()))?;
+            ensure_playable(&map)?;
+            load_context.set_default_asset(LoadedAsset::new(map));
             Ok(())
         })
     }
@@ -0,0 +1,105 @@
+//! Older versions of the `.map` format and the steps that upgrade them.
+//! Maps saved before versioning have no `version` field and are read as version 0 or 1,
+//! told apart by how their spawners name teams
+
+use serde::Deserialize;
+
+use super::{Map, MapBounds, Rect, MAP_VERSION};
+use crate::{prelude::*, rules::MatchRules};
+
+#[derive(Deserialize)]
+struct VersionProbe {
+    #[serde(default)]
+    version: u32,
+}
+
+/// The game jam format, spawners named the side they started near
+#[derive(Deserialize)]
+struct MapV0 {
+    rects: Vec<Rect>,
+    spawn_locations: Vec<(Vec2, ChickenOrDog)>,
+    player_spawn: Vec2,
+    enemy_spawn: Vec2,
+}
+
+#[derive(Deserialize)]
+enum ChickenOrDog {
+    Chicken,
+    Dog,
+}
+
+/// Numbered teams, no name, author or bounds
+#[derive(Deserialize)]
+struct MapV1 {
+    rects: Vec<Rect>,
+    spawn_locations: Vec<(Vec2, Team)>,
+    player_spawn: Vec2,
+    enemy_spawn: Vec2,
+    #[serde(default)]
+    hero_spawns: Vec<(Vec2, Team)>,
+}
+
+impl From<MapV0> for MapV1 {
+    fn from(map: MapV0) -> Self {
+        let spawn_locations = map
+            .spawn_locations
+            .into_iter()
+            .map(|(location, side)| match side {
+                ChickenOrDog::Chicken => (location, Team(0)),
+                ChickenOrDog::Dog => (location, Team(1)),
+            })
+            .collect();
+
+        Self {
+            rects: map.rects,
+            spawn_locations,
+            player_spawn: map.player_spawn,
+            enemy_spawn: map.enemy_spawn,
+            hero_spawns: Vec::new(),
+        }
+    }
+}
+
+impl MapV1 {
+    fn upgrade(self, name: &str) -> Map {
+        Map {
+            version: MAP_VERSION,
+            name: name.to_string(),
+            author: String::new(),
+            bounds: MapBounds::around(&self.rects),
+            rects: self.rects,
+            spawn_locations: self.spawn_locations,
+            player_spawn: self.player_spawn,
+            enemy_spawn: self.enemy_spawn,
+            hero_spawns: self.hero_spawns,
+            rules: MatchRules::default(),
+        }
+    }
+}
+
+pub fn upgrade(bytes: &[u8], name: &str) -> anyhow::Result<Map> {
+    // A file that can't even be probed is reported by the parse of the version it claims
+    let version = ron::de::from_bytes::<VersionProbe>(bytes)
+        .map(|probe| probe.version)
+        .unwrap_or_default();
+
+    match version {
+        0 | 1 => {
+            let map = match ron::de::from_bytes::<MapV1>(bytes) {
+                Ok(map) => map,
+                // Report the newer format's error, it's the one a hand edited map was aiming for
+                Err(e) => ron::de::from_bytes::<MapV0>(bytes)
+                    .map(MapV1::from)
+                    .map_err(|_| e)?,
+            };
+            info!("Upgrading map {} from an unversioned file", name);
+            Ok(map.upgrade(name))
+        }
+        MAP_VERSION => Ok(ron::de::from_bytes::<Map>(bytes)?),
+        version => anyhow::bail!(
+            "map version {} is newer than the supported version {}",
+            version,
+            MAP_VERSION
+        ),
+    }
+}
@@ -0,0 +1,246 @@
+use std::fmt;
+
+use super::Map;
+use crate::{navigation::NavGrid, prelude::*};
+
+/// Size of the cells reachability is checked on, small enough that the thinnest walls still block
+const REACH_CELL_SIZE: f32 = 0.05;
+
+/// Something placed on the map
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpawnPoint {
+    Player,
+    Enemy,
+    /// Index into `Map::hero_spawns`
+    Hero(usize),
+    /// Index into `Map::spawn_locations`
+    Spawner(usize),
+}
+
+impl fmt::Display for SpawnPoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SpawnPoint::Player => write!(f, "the player spawn"),
+            SpawnPoint::Enemy => write!(f, "the enemy spawn"),
+            SpawnPoint::Hero(index) => write!(f, "hero spawn {}", index),
+            SpawnPoint::Spawner(index) => write!(f, "spawner {}", index),
+        }
+    }
+}
+
+/// A problem found by [`validate`], rects and spawns are referred to by their index in the map
+#[derive(Debug, Clone, PartialEq)]
+pub enum MapIssue {
+    ZeroSizeRect {
+        rect: usize,
+        position: Vec2,
+    },
+    OverlappingRects {
+        a: usize,
+        b: usize,
+        position: Vec2,
+    },
+    SpawnInWall {
+        spawn: SpawnPoint,
+        rect: usize,
+        position: Vec2,
+    },
+    /// `team`'s hero can't walk from its spawn to the spawner
+    UnreachableSpawner {
+        spawner: usize,
+        team: Team,
+        position: Vec2,
+    },
+    NoSpawners,
+}
+
+impl MapIssue {
+    /// Fatal issues stop the map from being played, the rest are only reported
+    pub fn is_fatal(&self) -> bool {
+        !matches!(
+            self,
+            MapIssue::ZeroSizeRect { .. } | MapIssue::OverlappingRects { .. }
+        )
+    }
+}
+
+impl fmt::Display for MapIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MapIssue::ZeroSizeRect { rect, position } => write!(
+                f,
+                "wall {} at {} has no area, resize or delete it",
+                rect, position
+            ),
+            MapIssue::OverlappingRects { a, b, position } => write!(
+                f,
+                "walls {} and {} overlap near {}, merge them or pull them apart",
+                a, b, position
+            ),
+            MapIssue::SpawnInWall {
+                spawn,
+                rect,
+                position,
+            } => write!(
+                f,
+                "{} at {} is inside wall {}, move it into the open",
+                spawn, position, rect
+            ),
+            MapIssue::UnreachableSpawner {
+                spawner,
+                team,
+                position,
+            } => write!(
+                f,
+                "spawner {} at {} can't be reached from the hero spawn of team {}, open a path to it",
+                spawner, position, team.0
+            ),
+            MapIssue::NoSpawners => write!(
+                f,
+                "the map has no spawners, add at least one so there is something to capture"
+            ),
+        }
+    }
+}
+
+/// Checks that `map` can be played, returning every issue found
+pub fn validate(map: &Map) -> Vec<MapIssue> {
+    let mut issues = Vec::new();
+
+    for (index, rect) in map.rects.iter().enumerate() {
+        if rect.size.x.abs() <= f32::EPSILON || rect.size.y.abs() <= f32::EPSILON {
+            issues.push(MapIssue::ZeroSizeRect {
+                rect: index,
+                position: rect.position,
+            });
+        }
+    }
+    for (a, rect) in map.rects.iter().enumerate() {
+        for (b, other) in map.rects.iter().enumerate().skip(a + 1) {
+            if rect.overlaps(other) {
+                issues.push(MapIssue::OverlappingRects {
+                    a,
+                    b,
+                    position: (rect.position + other.position) / 2.0,
+                });
+            }
+        }
+    }
+
+    let heroes = [
+        (SpawnPoint::Player, map.player_spawn),
+        (SpawnPoint::Enemy, map.enemy_spawn),
+    ]
+    .into_iter()
+    .chain(
+        map.hero_spawns
+            .iter()
+            .enumerate()
+            .map(|(index, (location, _))| (SpawnPoint::Hero(index), *location)),
+    );
+    let spawners = map
+        .spawn_locations
+        .iter()
+        .enumerate()
+        .map(|(index, (location, _))| (SpawnPoint::Spawner(index), *location));
+    for (spawn, position) in heroes.chain(spawners) {
+        if let Some(rect) = map.rects.iter().position(|rect| rect.contains(position)) {
+            issues.push(MapIssue::SpawnInWall {
+                spawn,
+                rect,
+                position,
+            });
+        }
+    }
+
+    if map.spawn_locations.is_empty() {
+        issues.push(MapIssue::NoSpawners);
+    } else {
+        let grid = NavGrid::bake(map, REACH_CELL_SIZE, 0.0);
+        // Each hero has to reach every spawner on its own, another team reaching it doesn't help
+        for team in map.teams() {
+            let start = map.hero_spawn(team);
+            // Spawns inside walls are already reported
+            if grid.blocked(start) {
+                continue;
+            }
+            let reached = grid.reachable(std::iter::once(start));
+            for (index, (location, _)) in map.spawn_locations.iter().enumerate() {
+                if grid.blocked(*location) {
+                    continue;
+                }
+                if !reached[grid.cell(*location)] {
+                    issues.push(MapIssue::UnreachableSpawner {
+                        spawner: index,
+                        team,
+                        position: *location,
+                    });
+                }
+            }
+        }
+    }
+
+    issues
+}
+
+/// Warns about minor issues with `map` and fails listing the fatal ones
+pub fn ensure_playable(map: &Map) -> anyhow::Result<()> {
+    let (fatal, minor): (Vec<_>, Vec<_>) = validate(map).into_iter().partition(MapIssue::is_fatal);
+    // Walls overlap at most joints, so these would flood the log one by one
+    if !minor.is_empty() {
+        warn!(
+            "Map {} has {} minor issues, open it in the mapping editor to review them",
+            map.name,
+            minor.len()
+        );
+    }
+
+    if fatal.is_empty() {
+        Ok(())
+    } else {
+        let fatal: Vec<_> = fatal.iter().map(MapIssue::to_string).collect();
+        anyhow::bail!("map {} can't be played: {}", map.name, fatal.join("; "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::Rect;
+
+    fn wall(x: f32, y: f32, width: f32, height: f32) -> Rect {
+        Rect {
+            position: Vec2::new(x, y),
+            size: Vec2::new(width, height),
+            rotation: 0.0,
+        }
+    }
+
+    #[test]
+    fn reports_which_team_cant_reach_a_spawner() {
+        let mut map = Map::new("boxed in");
+        map.player_spawn = Vec2::new(-1.0, 0.0);
+        map.enemy_spawn = Vec2::new(1.0, 0.0);
+        map.spawn_locations.push((Vec2::new(-1.0, 0.5), Team(0)));
+        // A closed box around the enemy spawn
+        map.rects.extend([
+            wall(1.0, 0.3, 0.7, 0.1),
+            wall(1.0, -0.3, 0.7, 0.1),
+            wall(0.7, 0.0, 0.1, 0.5),
+            wall(1.3, 0.0, 0.1, 0.5),
+        ]);
+
+        let unreachable: Vec<_> = validate(&map)
+            .into_iter()
+            .filter(|issue| matches!(issue, MapIssue::UnreachableSpawner { .. }))
+            .collect();
+        assert_eq!(
+            unreachable,
+            vec![MapIssue::UnreachableSpawner {
+                spawner: 0,
+                team: Team(1),
+                position: Vec2::new(-1.0, 0.5),
+            }]
+        );
+    }
+}
@@ -13,7 +13,7 @@ use kayak_ui::{
     widgets,
 };
 
-use crate::{GameState, SCALE};
+use crate::{difficulty::Difficulty, pause::PauseAction, tuning::GameTuning, GameState, SCALE};
 
 pub struct MenuPlugin;
 
@@ -25,7 +25,9 @@ impl Plugin for MenuPlugin {
             .add_system_set(SystemSet::on_enter(GameState::MainMenu).with_system(spawn_main_menu))
             .add_system_set(SystemSet::on_exit(GameState::MainMenu).with_system(destroy_ui))
             .add_system_set(SystemSet::on_enter(GameState::Tutorial).with_system(spawn_tutorial))
-            .add_system_set(SystemSet::on_exit(GameState::Tutorial).with_system(destroy_ui));
+            .add_system_set(SystemSet::on_exit(GameState::Tutorial).with_system(destroy_ui))
+            .add_system_set(SystemSet::on_enter(GameState::Paused).with_system(spawn_pause_menu))
+            .add_system_set(SystemSet::on_exit(GameState::Paused).with_system(destroy_ui));
     }
 }
 
@@ -154,6 +156,7 @@ fn spawn_main_menu(
                     //<widgets::Button styles={Some(button_style)}>
                         //<widgets::Text content={"Options".to_string()} size={24.0} />
                     //</widgets::Button>
+                    <DifficultyButton text_size={24.0 * SCALE}/>
                     <widgets::Element styles={Some(element_style)}>
                         //<widgets::Text content={"Volume: ".to_string()} size={24.0} />
                     <widgets::Image handle={volume_handle} styles={Some(image_volume_style)}/>
@@ -252,6 +255,45 @@ fn SliderBox(props: SliderBoxProps) {
     }
 }
 
+#[derive(WidgetProps, Default, Debug, PartialEq, Clone)]
+pub struct DifficultyButtonProps {
+    text_size: f32,
+}
+
+/// Cycles through the difficulty levels when clicked
+#[widget]
+fn DifficultyButton(props: DifficultyButtonProps) {
+    let current = context.query_world::<Res<Difficulty>, _, _>(|difficulty| difficulty.level);
+    let (level, set_level, ..) = use_state!(current);
+
+    let on_click = OnEvent::new(move |context, event| {
+        if let EventType::Click(..) = event.event_type {
+            let next = level.next();
+            set_level(next);
+            context.query_world::<(ResMut<Difficulty>, Res<GameTuning>), _, _>(
+                |(mut difficulty, tuning)| {
+                    *difficulty = Difficulty::new(next, &tuning);
+                },
+            );
+        }
+    });
+
+    let button_style = Style {
+        width: StyleProp::Value(Units::Percentage(80.0 * SCALE)),
+        height: StyleProp::Value(Units::Percentage(10.0 * SCALE)),
+        top: StyleProp::Value(Units::Pixels(5.0)),
+        //Centers children
+        padding: StyleProp::Value(Edge::all(Units::Stretch(1.0))),
+        ..default()
+    };
+
+    rsx! {
+        <widgets::Button styles={Some(button_style)} on_event={Some(on_click)}>
+            <widgets::Text content={format!("Difficulty: {}", level)} size={props.text_size} />
+        </widgets::Button>
+    }
+}
+
 fn spawn_tutorial(
     mut commands: Commands,
     assets: Res<AssetServer>,
@@ -307,3 +349,132 @@ fn spawn_tutorial(
 
     commands.insert_resource(context);
 }
+
+fn spawn_pause_menu(mut commands: Commands) {
+    let context = BevyContext::new(|context| {
+        let container_style = Style {
+            layout_type: StyleProp::Value(LayoutType::Column),
+            width: StyleProp::Value(Units::Percentage(30.0)),
+            height: StyleProp::Value(Units::Percentage(45.0)),
+
+            border_radius: StyleProp::Value(Corner::all(10.0)),
+            background_color: StyleProp::Value(Color::new(0.6, 0.4, 0.3, 0.9)),
+            //Centers
+            left: StyleProp::Value(Units::Stretch(1.0)),
+            right: StyleProp::Value(Units::Stretch(1.0)),
+            top: StyleProp::Value(Units::Stretch(1.0)),
+            bottom: StyleProp::Value(Units::Stretch(1.0)),
+            //Centers children
+            padding: StyleProp::Value(Edge::all(Units::Stretch(1.0))),
+            ..default()
+        };
+
+        render! {
+            <widgets::App>
+                <widgets::Background styles={Some(container_style)}>
+                    <widgets::Text content={"Paused".to_string()} size={32.0 * SCALE} />
+                    <PauseButton text={"Resume".to_string()} action={Some(PauseAction::Resume)}/>
+                    <PauseButton text={"Restart".to_string()} action={Some(PauseAction::Restart)}/>
+                    <PauseSettings />
+                    <PauseButton text={"Quit to menu".to_string()} action={Some(PauseAction::QuitToMenu)}/>
+                </widgets::Background>
+            </widgets::App>
+        }
+    });
+
+    commands.insert_resource(context);
+}
+
+#[derive(WidgetProps, Default, Debug, PartialEq, Clone)]
+pub struct PauseButtonProps {
+    text: String,
+    action: Option<PauseAction>,
+}
+
+/// Hands its action to the game, which carries it out outside of the UI
+#[widget]
+fn PauseButton(props: PauseButtonProps) {
+    let action = props.action;
+    let on_click = OnEvent::new(move |context, event| {
+        if let (EventType::Click(..), Some(action)) = (event.event_type, action) {
+            context.query_world::<Commands, _, _>(|mut commands| {
+                commands.insert_resource(action);
+            });
+        }
+    });
+
+    let button_style = Style {
+        width: StyleProp::Value(Units::Percentage(80.0 * SCALE)),
+        height: StyleProp::Value(Units::Percentage(10.0 * SCALE)),
+        top: StyleProp::Value(Units::Pixels(5.0)),
+        //Centers children
+        padding: StyleProp::Value(Edge::all(Units::Stretch(1.0))),
+        ..default()
+    };
+
+    rsx! {
+        <widgets::Button styles={Some(button_style)} on_event={Some(on_click)}>
+            <widgets::Text content={props.text.clone()} size={24.0 * SCALE} />
+        </widgets::Button>
+    }
+}
+
+/// A button that shows and hides the volume slider
+#[widget]
+fn PauseSettings() {
+    let (open, set_open, ..) = use_state!(false);
+
+    let on_click = OnEvent::new(move |_, event| {
+        if let EventType::Click(..) = event.event_type {
+            set_open(!open);
+        }
+    });
+
+    let button_style = Style {
+        width: StyleProp::Value(Units::Percentage(80.0 * SCALE)),
+        height: StyleProp::Value(Units::Percentage(10.0 * SCALE)),
+        top: StyleProp::Value(Units::Pixels(5.0)),
+        //Centers children
+        padding: StyleProp::Value(Edge::all(Units::Stretch(1.0))),
+        ..default()
+    };
+
+    let settings_style = Style {
+        layout_type: StyleProp::Value(LayoutType::Column),
+        width: StyleProp::Value(Units::Percentage(100.0)),
+        height: StyleProp::Value(Units::Auto),
+        //Centers children
+        padding: StyleProp::Value(Edge::all(Units::Stretch(1.0))),
+        ..default()
+    };
+
+    let element_style = Style {
+        layout_type: StyleProp::Value(LayoutType::Row),
+        width: StyleProp::Value(Units::Percentage(100.0)),
+        height: StyleProp::Value(Units::Percentage(10.0)),
+        padding: StyleProp::Value(Edge {
+            left: Units::Stretch(1.0),
+            right: Units::Stretch(1.0),
+            top: Units::Pixels(10.0),
+            bottom: Units::Stretch(1.0),
+        }),
+        ..default()
+    };
+
+    let box_color = Color::WHITE;
+    let button_color = Color::new(0.9, 0.1, 0.1, 1.0);
+
+    rsx! {
+        <widgets::Element styles={Some(settings_style)}>
+            <widgets::Button styles={Some(button_style)} on_event={Some(on_click)}>
+                <widgets::Text content={"Settings".to_string()} size={24.0 * SCALE} />
+            </widgets::Button>
+            <widgets::If condition={open}>
+                <widgets::Element styles={Some(element_style)}>
+                    <widgets::Text content={"Volume".to_string()} size={24.0 * SCALE} />
+                    <SliderBox size={(200.0, 30.0)} box_color={box_color} button_color={button_color}/>
+                </widgets::Element>
+            </widgets::If>
+        </widgets::Element>
+    }
+}
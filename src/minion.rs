@@ -1,6 +1,19 @@
-use crate::prelude::*;
+use crate::{
+    assets::BulletFrames,
+    bullet::{spawn_bullet, BulletStats},
+    damage::{ApplyDamageLabel, DamageRequest},
+    economy::{Bank, Income, LastAttacker},
+    events::{BulletFired, UnitDied},
+    navigation::{NavGrid, NavPath},
+    prelude::*,
+    squad::{formation_offset, separation, SquadMember, SquadOrder, SquadOrders},
+    targeting::{pick_target, MinionTarget, TargetCandidate, TargetKind},
+};
 use heron::rapier_plugin::PhysicsWorld;
-use serde::{Deserialize, Serialize};
+use rand::Rng;
+
+/// Minions marching on a spawner break off to fight enemies closer than this
+const ENGAGE_RADIUS: f32 = 0.6;
 
 pub struct MinionPlugin;
 impl Plugin for MinionPlugin {
@@ -8,252 +21,431 @@ impl Plugin for MinionPlugin {
         app.add_system_set(
             SystemSet::on_update(GameState::GamePlay)
                 .with_system(minions_ai)
-                .with_system(minions_attack),
+                .with_system(minions_attack.before(ApplyDamageLabel))
+                .with_system(minions_shoot)
+                .with_system(minions_heal),
         )
         .add_system(minion_death)
         .register_type::<Spawner>();
     }
 }
 
-#[derive(Bundle)]
-pub struct MinionBundle {
-    #[bundle]
-    sprite: SpriteSheetBundle,
-    movement_stats: MovementStats,
-    minion_type: ChickenOrDog,
-    minion: Minion,
-    hp: Health,
-    rigid_body: RigidBody,
-    collision_shape: CollisionShape,
-    rotation_constraints: RotationConstraints,
-    collision_layer: CollisionLayers,
-}
-
-#[derive(Serialize, Deserialize)]
-pub struct DogMinionConfig {
-    speed: f32,
-    hp: f32,
-}
-
-#[derive(Serialize, Deserialize)]
-pub struct ChickenMinionConfig {
-    speed: f32,
-    hp: f32,
-}
-
-impl MinionBundle {
-    pub fn spawn_dog_minion(
-        commands: &mut Commands,
-        assets: &Res<OurAssets>,
-        spawn_location: Vec2,
-    ) -> anyhow::Result<Entity> {
-        let config: DogMinionConfig =
-            ron::de::from_str(include_str!("../assets/config/dog_minion.ron"))
-                .context("failed to deserialize DogMinionConfig")?;
-        let size = 0.15;
-
-        let ent = commands
-            .spawn_bundle(MinionBundle {
-                sprite: SpriteSheetBundle {
-                    texture_atlas: assets.placeholder_atlas.clone(),
-                    sprite: TextureAtlasSprite {
-                        color: Color::SALMON,
-                        custom_size: Some(Vec2::splat(size)),
-                        ..default()
-                    },
-                    transform: Transform::from_translation(Vec3::new(
-                        spawn_location.x,
-                        spawn_location.y,
-                        100.0,
-                    )),
-                    ..default()
-                },
-                movement_stats: MovementStats {
-                    speed: config.speed,
-                },
-                minion_type: ChickenOrDog::Dog,
-                minion: Minion {
-                    attack_cooldown: Timer::from_seconds(MINION_MELEE_COOLDOWN, true),
-                },
-                hp: Health(config.hp),
-                rigid_body: RigidBody::Dynamic,
-                collision_shape: CollisionShape::Sphere { radius: size / 2.0 },
-                rotation_constraints: RotationConstraints::lock(),
-                collision_layer: CollisionLayers::all_masks::<Layer>().with_group(Layer::Enemy),
-            })
-            .insert(Name::new("Puppy"))
-            .id();
-        Ok(ent)
-    }
-
-    pub fn spawn_chicken_minion(
-        commands: &mut Commands,
-        assets: &Res<OurAssets>,
-        spawn_location: Vec2,
-    ) -> anyhow::Result<Entity> {
-        let config: ChickenMinionConfig =
-            ron::de::from_str(include_str!("../assets/config/chicken_minion.ron"))
-                .context("failed to deserialize ChickenMinionConfig")?;
-        let size = 0.15;
-
-        let ent = commands
-            .spawn_bundle(MinionBundle {
-                sprite: SpriteSheetBundle {
-                    texture_atlas: assets.placeholder_atlas.clone(),
-                    sprite: TextureAtlasSprite {
-                        color: Color::GREEN,
-                        ..default()
-                    },
-                    transform: Transform::from_translation(Vec3::new(
-                        spawn_location.x,
-                        spawn_location.y,
-                        100.0,
-                    )),
-                    ..default()
-                },
-                movement_stats: MovementStats {
-                    speed: config.speed,
-                },
-                minion_type: ChickenOrDog::Chicken,
-                minion: Minion {
-                    attack_cooldown: Timer::from_seconds(MINION_MELEE_COOLDOWN, true),
-                },
-                hp: Health(config.hp),
-                rigid_body: RigidBody::Dynamic,
-                collision_shape: CollisionShape::Sphere { radius: size / 2.0 },
-                rotation_constraints: RotationConstraints::lock(),
-                collision_layer: CollisionLayers::all_masks::<Layer>().with_group(Layer::Player),
-            })
-            .insert(Name::new("Chick"))
-            .id();
-        Ok(ent)
-    }
-}
-
 /// # Minion AI
-/// - Minions look for the closest enemy, enemy minion, or capturable spawner
+/// - Minions follow their team's `SquadOrder`, keeping to their slot in their squad's formation
+/// - Free minions go for the target `MinionTargeting` ranks highest, sticking with the one they have
+/// - They won't chase heroes and minions further than the leash from their home spawner
 /// - If there is no other targets, they follow the player
+/// - Minions attacking a spawner fight anything that comes close on the way
+/// - They walk around walls by following a path on the `NavGrid` and keep apart from each other
 pub fn minions_ai(
     mut minion_query: Query<
         (
-            &ChickenOrDog,
+            Entity,
+            &Team,
             &GlobalTransform,
             &mut Transform,
             &mut Animation,
             &MovementStats,
+            &mut NavPath,
+            &mut MinionTarget,
+            Option<&SquadMember>,
+            Option<&RangedAttack>,
+            Option<&Healer>,
         ),
-        (With<Minion>, Without<Spawner>),
+        (With<Minion>, Without<Spawner>, Without<Stunned>),
     >,
     targets_query: Query<
-        (&GlobalTransform, Option<&ChickenOrDog>),
+        (
+            Entity,
+            &GlobalTransform,
+            Option<&Team>,
+            Option<&Spawner>,
+            Option<&Minion>,
+            Option<&RespawnTimer>,
+        ),
         Or<(With<Spawner>, With<Player>, With<Enemy>, With<Minion>)>,
     >,
-    player_query: Query<&GlobalTransform, With<Player>>,
+    spawners: Query<(&GlobalTransform, Option<&Team>), With<Spawner>>,
     physics_world: PhysicsWorld,
-    time: Res<Time>,
+    nav_grid: Option<Res<NavGrid>>,
+    orders: Res<SquadOrders>,
+    tuning: Res<GameTuning>,
+    time: Res<GameTime>,
 ) {
-    for (minion_type, global_transform, mut transform, mut animation, movement_stats) in
-        minion_query.iter_mut()
+    let targeting = &tuning.minion_targeting;
+    let minion_positions: Vec<(Entity, Vec2)> = minion_query
+        .iter()
+        .map(|(entity, _, transform, ..)| (entity, transform.translation.truncate()))
+        .collect();
+
+    for (
+        entity,
+        minion_type,
+        global_transform,
+        mut transform,
+        mut animation,
+        movement_stats,
+        mut nav_path,
+        mut current_target,
+        squad,
+        ranged,
+        healer,
+    ) in minion_query.iter_mut()
     {
         let position = global_transform.translation.truncate();
+        let offset = squad
+            .map(|member| formation_offset(member.slot))
+            .unwrap_or_default();
+        let home = squad
+            .and_then(|member| spawners.get(member.squad).ok())
+            .map(|(transform, _)| transform.translation.truncate());
 
-        let enemy_targets = targets_query
+        let candidates: Vec<TargetCandidate> = targets_query
             .iter()
-            .filter_map(|(transform, target_minion_type)| match target_minion_type {
-                None => Some(*transform),
-                Some(ty) if ty != minion_type => Some(*transform),
-                _ => None,
+            .filter(|(.., respawn)| !respawn.map(|respawn| respawn.is_dead).unwrap_or(false))
+            .filter_map(|(target, transform, team, spawner, minion, _)| {
+                let kind =
+                    TargetKind::classify(*minion_type, team.copied(), spawner, minion.is_some())?;
+                Some(TargetCandidate {
+                    entity: target,
+                    kind,
+                    position: transform.translation.truncate(),
+                })
             })
-            .filter(|transform| {
-                physics_world
-                    .ray_cast_with_filter(
-                        position.extend(0.0),
-                        transform.translation - position.extend(0.0),
-                        false,
-                        CollisionLayers::none()
-                            .with_group(Layer::Wall)
-                            .with_mask(Layer::Wall),
-                        |_ent| true,
-                    )
-                    .is_none()
-            });
+            // Healers don't pick fights, they go wherever their squad goes
+            .filter(|candidate| healer.is_none() || !candidate.kind.is_unit())
+            .filter(|candidate| targeting.within_leash(candidate.kind, candidate.position, home))
+            .filter(|candidate| has_line_of_sight(&physics_world, position, candidate.position))
+            .collect();
 
-        let target_position = {
-            if let Some(closest_target) = find_closest(position, enemy_targets) {
-                closest_target
-            } else {
-                player_query.single().translation.truncate()
+        let (target, target_position) = match orders.get(*minion_type) {
+            SquadOrder::Free => {
+                match pick_target(&candidates, position, current_target.0, targeting) {
+                    Some(target) => (Some(target), target.position),
+                    // Nothing to go for, so it waits by its spawner
+                    None => (None, home.map(|home| home + offset).unwrap_or(position)),
+                }
             }
+            SquadOrder::Rally(point) => (None, point + offset),
+            SquadOrder::AttackSpawner => {
+                let nearby: Vec<TargetCandidate> = candidates
+                    .iter()
+                    .filter(|candidate| {
+                        candidate.kind.is_unit()
+                            && candidate.position.distance(position) < ENGAGE_RADIUS
+                    })
+                    .copied()
+                    .collect();
+                match pick_target(&nearby, position, current_target.0, targeting) {
+                    Some(target) => (Some(target), target.position),
+                    None => {
+                        let enemy_spawners = spawners
+                            .iter()
+                            .filter(|(_, owner)| *owner != Some(minion_type))
+                            .map(|(transform, _)| *transform);
+                        let spawner = find_closest(home.unwrap_or(position), enemy_spawners)
+                            .map(|spawner| spawner + offset)
+                            .unwrap_or(position);
+                        (None, spawner)
+                    }
+                }
+            }
+            SquadOrder::Hold => (None, position),
         };
+        current_target.0 = target.map(|target| target.entity);
 
-        let dir = target_position - position;
-        let dir = dir.try_normalize().unwrap_or_default().extend(0.0);
-        if !animation.playing_alt {
-            animation.flip_x = dir.x > 0.0;
-            if minion_type == &ChickenOrDog::Dog {
-                animation.flip_x = !animation.flip_x;
+        // Shooters stop once their target is well in range rather than walking into it
+        let target_position = match (target, ranged) {
+            (Some(target), Some(ranged))
+                if target.kind.is_unit()
+                    && target.position.distance(position) < ranged.range * 0.8 =>
+            {
+                position
             }
+            _ => target_position,
+        };
+
+        let dir = if target_position == position {
+            Vec2::ZERO
+        } else {
+            nav_path.steer(
+                nav_grid.as_deref(),
+                position,
+                target_position,
+                time.delta_seconds(),
+            )
+        };
+        let push = separation(
+            position,
+            minion_positions
+                .iter()
+                .filter(|(other, _)| *other != entity)
+                .map(|(_, other_position)| *other_position),
+        );
+        let dir = (dir + push).clamp_length_max(1.0).extend(0.0);
+        if !animation.playing_alt && dir.x != 0.0 {
+            animation.flip_x = (dir.x > 0.0) != animation.faces_right;
         }
         transform.translation += dir * movement_stats.speed * time.delta_seconds();
     }
 }
 
-fn minion_death(minions: Query<(Entity, &Health), With<Minion>>, mut commands: Commands) {
-    for (ent, health) in minions.iter() {
+fn minion_death(
+    minions: Query<(Entity, &Health, &Team, Option<&LastAttacker>), With<Minion>>,
+    mut bank: Bank,
+    mut died: EventWriter<UnitDied>,
+    tuning: Res<GameTuning>,
+    mut commands: Commands,
+) {
+    for (ent, health, team, last_attacker) in minions.iter() {
         if health.0 <= 0.0 {
+            let killer = last_attacker.and_then(|attacker| attacker.0);
+            if let Some(killer) = killer {
+                bank.earn(killer, tuning.economy.minion_bounty, Income::MinionKill);
+            }
+            died.send(UnitDied {
+                unit: ent,
+                team: *team,
+                killer,
+                hero: false,
+            });
             commands.entity(ent).despawn_recursive();
         }
     }
 }
 
+/// Minions hit the enemy in reach that their targeting ranks highest, preferring the one they're after
 fn minions_attack(
-    mut minions: Query<(&mut Minion, &GlobalTransform, &ChickenOrDog, &mut Animation)>,
-    mut targets: Query<
+    mut minions: Query<
         (
+            Entity,
+            &mut MeleeAttack,
             &GlobalTransform,
-            &ChickenOrDog,
-            &mut Health,
-            Option<&mut DamageFlash>,
+            &Team,
+            &mut Animation,
+            &MinionTarget,
+        ),
+        (With<Minion>, Without<Stunned>),
+    >,
+    targets: Query<
+        (
+            Entity,
+            &GlobalTransform,
+            &Team,
+            Option<&Minion>,
+            Option<&RespawnTimer>,
         ),
         Or<(With<Player>, With<Minion>, With<Enemy>)>,
     >,
+    mut damage: EventWriter<DamageRequest>,
+    tuning: Res<GameTuning>,
+    time: Res<GameTime>,
+) {
+    let delta = time.delta();
+
+    for (minion_ent, mut attack, global_transform, team, mut animation, current_target) in
+        minions.iter_mut()
+    {
+        if !attack.cooldown.finished() {
+            attack.cooldown.tick(delta);
+            continue;
+        }
 
-    time: Res<Time>,
+        let position = global_transform.translation.truncate();
+
+        let in_reach: Vec<TargetCandidate> = targets
+            .iter()
+            .filter(|(.., respawn)| !respawn.map(|respawn| respawn.is_dead).unwrap_or(false))
+            .filter(|(_, _, enemy_team, ..)| *enemy_team != team)
+            .map(|(entity, target_transform, _, minion, _)| TargetCandidate {
+                entity,
+                kind: if minion.is_some() {
+                    TargetKind::Minion
+                } else {
+                    TargetKind::Hero
+                },
+                position: target_transform.translation.truncate(),
+            })
+            .filter(|candidate| candidate.position.distance(position) <= attack.range)
+            .collect();
+        let target = match pick_target(
+            &in_reach,
+            position,
+            current_target.0,
+            &tuning.minion_targeting,
+        ) {
+            Some(target) => target,
+            None => continue,
+        };
+
+        animation.flip_x = (target.position.x - position.x > 0.0) != animation.faces_right;
+        attack.cooldown.tick(time.delta());
+        animation.playing_alt = true;
+        animation.current_frame = 0;
+        let direction = (target.position - position)
+            .try_normalize()
+            .unwrap_or(Vec2::X);
+        damage.send(DamageRequest {
+            source: minion_ent,
+            target: target.entity,
+            team: *team,
+            amount: attack.damage,
+            knockback: direction * tuning.damage.melee_knockback,
+        });
+    }
+}
+
+/// Minions with a `RangedAttack` shoot the enemy in range their targeting ranks highest
+fn minions_shoot(
+    mut commands: Commands,
+    mut minions: Query<
+        (
+            &mut RangedAttack,
+            &GlobalTransform,
+            &Team,
+            &mut Animation,
+            &MinionTarget,
+        ),
+        (With<Minion>, Without<Stunned>),
+    >,
+    targets: Query<
+        (
+            Entity,
+            &GlobalTransform,
+            &Team,
+            Option<&Minion>,
+            Option<&RespawnTimer>,
+        ),
+        Or<(With<Player>, With<Minion>, With<Enemy>)>,
+    >,
+    physics_world: PhysicsWorld,
+    tuning: Res<GameTuning>,
+    time: Res<GameTime>,
+    mut rng: ResMut<GameRng>,
+    mut fired: EventWriter<BulletFired>,
+    bullets: Option<Res<BulletFrames>>,
 ) {
     let delta = time.delta();
 
-    for (mut minion, global_transform, team, mut animation) in minions.iter_mut() {
-        if !minion.attack_cooldown.finished() {
-            minion.attack_cooldown.tick(delta);
+    for (mut attack, global_transform, team, mut animation, current_target) in minions.iter_mut() {
+        if !attack.cooldown.finished() {
+            attack.cooldown.tick(delta);
             continue;
         }
 
         let position = global_transform.translation.truncate();
 
-        let enemy_target = targets
-            .iter_mut()
-            .filter_map(|(target_transform, enemy_team, health, damage_flash)| {
-                let distance = (target_transform.translation.truncate() - position).length();
-                if team != enemy_team && distance <= MINION_MELEE_RANGE {
-                    animation.flip_x = target_transform.translation.x - position.x > 0.0;
-                    if team == &ChickenOrDog::Dog {
-                        animation.flip_x = !animation.flip_x;
-                    }
-                    Some((health, damage_flash))
+        let in_range: Vec<TargetCandidate> = targets
+            .iter()
+            .filter(|(.., respawn)| !respawn.map(|respawn| respawn.is_dead).unwrap_or(false))
+            .filter(|(_, _, enemy_team, ..)| *enemy_team != team)
+            .map(|(entity, target_transform, _, minion, _)| TargetCandidate {
+                entity,
+                kind: if minion.is_some() {
+                    TargetKind::Minion
                 } else {
-                    None
-                }
+                    TargetKind::Hero
+                },
+                position: target_transform.translation.truncate(),
             })
-            .next();
+            .filter(|candidate| candidate.position.distance(position) <= attack.range)
+            .filter(|candidate| has_line_of_sight(&physics_world, position, candidate.position))
+            .collect();
+        let target = match pick_target(
+            &in_range,
+            position,
+            current_target.0,
+            &tuning.minion_targeting,
+        ) {
+            Some(target) => target,
+            None => continue,
+        };
+
+        let direction = (target.position - position)
+            .try_normalize()
+            .unwrap_or(Vec2::X);
+        animation.flip_x = (direction.x > 0.0) != animation.faces_right;
+        animation.playing_alt = true;
+        animation.current_frame = 0;
+        attack.cooldown.tick(delta);
+
+        let mut transform = Transform::from_translation(global_transform.translation);
+        transform.translation.z += 1.0;
+        // Player bullets are the first pair of frames, everyone else's the second
+        let frame = rng.gen_range(0..2) + if *team == Team::PLAYER { 0 } else { 2 };
+        spawn_bullet(
+            &mut commands,
+            &mut fired,
+            transform,
+            *team,
+            BulletStats::from(&*attack),
+            direction,
+            bullets.as_deref().map(|bullets| (bullets, frame)),
+        );
+    }
+}
+
+/// Healers top up the most hurt ally in reach, ties go to the lowest entity
+fn minions_heal(
+    mut healers: Query<
+        (Entity, &mut Healer, &GlobalTransform, &Team, &mut Animation),
+        (With<Minion>, Without<Stunned>),
+    >,
+    mut allies: Query<
+        (
+            Entity,
+            &GlobalTransform,
+            &Team,
+            &mut Health,
+            &MaxHealth,
+            Option<&RespawnTimer>,
+        ),
+        Without<Spawner>,
+    >,
+    time: Res<GameTime>,
+) {
+    let delta = time.delta();
+
+    for (healer_ent, mut healer, global_transform, team, mut animation) in healers.iter_mut() {
+        if !healer.cooldown.finished() {
+            healer.cooldown.tick(delta);
+            continue;
+        }
+
+        let position = global_transform.translation.truncate();
 
-        if let Some((mut enemy_hp, enemy_flash)) = enemy_target {
-            minion.attack_cooldown.tick(time.delta());
-            animation.playing_alt = true;
-            animation.current_frame = 0;
-            enemy_hp.0 -= MINION_MELEE_DMG;
+        let patient = allies
+            .iter()
+            .filter(|(entity, _, ally_team, health, max_health, respawn)| {
+                *entity != healer_ent
+                    && *ally_team == team
+                    && health.0 < max_health.0
+                    && !respawn.map(|respawn| respawn.is_dead).unwrap_or(false)
+            })
+            .filter(|(_, transform, ..)| {
+                transform.translation.truncate().distance(position) <= healer.range
+            })
+            .map(|(entity, transform, _, health, max_health, _)| {
+                (
+                    entity,
+                    transform.translation.x,
+                    health.0 / max_health.0.max(f32::EPSILON),
+                )
+            })
+            .min_by(|(a, _, hurt_a), (b, _, hurt_b)| {
+                hurt_a
+                    .partial_cmp(hurt_b)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+                    .then(a.cmp(b))
+            });
 
-            if let Some(mut damage_flash) = enemy_flash {
-                damage_flash.timer = Timer::from_seconds(0.1, true);
+        if let Some((patient, patient_x, _)) = patient {
+            if let Ok((_, _, _, mut health, max_health, _)) = allies.get_mut(patient) {
+                health.0 = (health.0 + healer.amount).min(max_health.0);
+                animation.flip_x = (patient_x - position.x > 0.0) != animation.faces_right;
+                animation.playing_alt = true;
+                animation.current_frame = 0;
+                healer.cooldown.tick(delta);
             }
         }
     }
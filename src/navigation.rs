@@ -0,0 +1,420 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, VecDeque},
+};
+
+use crate::prelude::*;
+
+/// Size of a navigation cell, a little over the thinnest wall
+pub const NAV_CELL_SIZE: f32 = 0.1;
+/// How far paths keep from walls, about the radius of a minion
+pub const NAV_CLEARANCE: f32 = 0.08;
+
+/// Seconds before a unit plans a fresh path to a goal that hasn't moved
+const REPATH_TIME: f32 = 1.0;
+/// How far a goal can move before the path to it is planned again
+const REPATH_DISTANCE: f32 = 0.25;
+/// How close a unit gets to a waypoint before heading for the next
+const WAYPOINT_RADIUS: f32 = 0.05;
+
+// Costs of a straight and diagonal step, kept integral so paths are identical on every machine
+const STRAIGHT_COST: u32 = 10;
+const DIAGONAL_COST: u32 = 14;
+
+pub struct NavigationPlugin;
+
+impl Plugin for NavigationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(SystemSet::on_enter(GameState::GamePlay).with_system(bake_nav_grid));
+    }
+}
+
+fn bake_nav_grid(
+    map_assets: Res<Assets<Map>>,
+    current_map: Res<CurrentMap>,
+    mut commands: Commands,
+) {
+    //create_map reports a missing map
+    let map = match map_assets.get(current_map.0.clone()) {
+        Some(map) => map,
+        None => return,
+    };
+    commands.insert_resource(NavGrid::bake(map, NAV_CELL_SIZE, NAV_CLEARANCE));
+}
+
+/// Which cells of a map can be walked through, baked from its walls
+pub struct NavGrid {
+    origin: Vec2,
+    cell_size: f32,
+    width: usize,
+    height: usize,
+    walls: Vec<bool>,
+}
+
+impl NavGrid {
+    /// Marks every cell whose center is within `clearance` of a wall.
+    /// The grid covers everything placed on the map plus a ring of open cells to walk around it
+    pub fn bake(map: &Map, cell_size: f32, clearance: f32) -> Self {
+        let points = [
+            map.player_spawn,
+            map.enemy_spawn,
+            map.bounds.min,
+            map.bounds.max,
+        ]
+        .into_iter()
+        .chain(map.spawn_locations.iter().map(|(location, _)| *location))
+        .chain(map.hero_spawns.iter().map(|(location, _)| *location))
+        .chain(map.rects.iter().flat_map(|rect| rect.corners()));
+        let (min, max) = points.fold(
+            (Vec2::splat(f32::MAX), Vec2::splat(f32::MIN)),
+            |(min, max), point| (min.min(point), max.max(point)),
+        );
+        let margin = Vec2::splat(cell_size * 2.0 + clearance);
+        let origin = min - margin;
+        let cells = ((max + margin - origin) / cell_size).ceil();
+        let (width, height) = (cells.x as usize, cells.y as usize);
+
+        let mut grid = Self {
+            origin,
+            cell_size,
+            width,
+            height,
+            walls: vec![false; width * height],
+        };
+        for y in 0..height {
+            for x in 0..width {
+                let center = grid.center(y * width + x);
+                grid.walls[y * width + x] = map.rects.iter().any(|rect| {
+                    let local = Mat2::from_angle(-rect.rotation) * (center - rect.position);
+                    local.abs().cmple(rect.size / 2.0 + clearance).all()
+                });
+            }
+        }
+        grid
+    }
+
+    /// The cell `point` is in, points off the grid use the nearest edge cell
+    pub fn cell(&self, point: Vec2) -> usize {
+        let cell = ((point - self.origin) / self.cell_size).floor();
+        let x = (cell.x.max(0.0) as usize).min(self.width - 1);
+        let y = (cell.y.max(0.0) as usize).min(self.height - 1);
+        y * self.width + x
+    }
+
+    pub fn center(&self, cell: usize) -> Vec2 {
+        let (x, y) = (cell % self.width, cell / self.width);
+        self.origin + (Vec2::new(x as f32, y as f32) + Vec2::splat(0.5)) * self.cell_size
+    }
+
+    pub fn blocked(&self, point: Vec2) -> bool {
+        self.walls[self.cell(point)]
+    }
+
+    /// Every cell that can be walked to from any of `starts`, indexed by cell
+    pub fn reachable(&self, starts: impl Iterator<Item = Vec2>) -> Vec<bool> {
+        let mut reached = vec![false; self.walls.len()];
+        let mut open = VecDeque::new();
+        for start in starts {
+            let cell = self.cell(start);
+            if !self.walls[cell] && !reached[cell] {
+                reached[cell] = true;
+                open.push_back(cell);
+            }
+        }
+
+        while let Some(cell) = open.pop_front() {
+            for (next, _) in self.neighbours(cell) {
+                if !reached[next] {
+                    reached[next] = true;
+                    open.push_back(next);
+                }
+            }
+        }
+        reached
+    }
+
+    /// A* from `from` to `to`, smoothed down to the corners a unit has to turn at.
+    /// Ends exactly on `to` and doesn't include `from`.
+    /// Ends inside walls are moved to the nearest open cell
+    pub fn find_path(&self, from: Vec2, to: Vec2) -> Option<Vec<Vec2>> {
+        let start = self.nearest_open(self.cell(from))?;
+        let goal = self.nearest_open(self.cell(to))?;
+        if start == goal {
+            return Some(vec![to]);
+        }
+
+        let heuristic = |cell: usize| {
+            let dx =
+                ((cell % self.width) as i64 - (goal % self.width) as i64).unsigned_abs() as u32;
+            let dy =
+                ((cell / self.width) as i64 - (goal / self.width) as i64).unsigned_abs() as u32;
+            STRAIGHT_COST * dx.max(dy) + (DIAGONAL_COST - STRAIGHT_COST) * dx.min(dy)
+        };
+
+        let mut cost = vec![u32::MAX; self.walls.len()];
+        let mut came_from = vec![usize::MAX; self.walls.len()];
+        // Ties go to the lowest cell so the search order never depends on the heap
+        let mut open = BinaryHeap::new();
+        cost[start] = 0;
+        open.push(Reverse((heuristic(start), start)));
+
+        while let Some(Reverse((estimate, cell))) = open.pop() {
+            if cell == goal {
+                break;
+            }
+            // A cheaper way here was found after this entry was queued
+            if estimate > cost[cell] + heuristic(cell) {
+                continue;
+            }
+            for (next, step) in self.neighbours(cell) {
+                let next_cost = cost[cell] + step;
+                if next_cost < cost[next] {
+                    cost[next] = next_cost;
+                    came_from[next] = cell;
+                    open.push(Reverse((next_cost + heuristic(next), next)));
+                }
+            }
+        }
+        if cost[goal] == u32::MAX {
+            return None;
+        }
+
+        let mut cells = vec![goal];
+        while let Some(&cell) = cells.last() {
+            if cell == start {
+                break;
+            }
+            cells.push(came_from[cell]);
+        }
+        cells.reverse();
+
+        let mut points: Vec<Vec2> = cells.into_iter().map(|cell| self.center(cell)).collect();
+        points[0] = from;
+        *points.last_mut().unwrap() = to;
+        Some(self.smooth(points))
+    }
+
+    /// Drops every waypoint that can be skipped by walking straight to a later one
+    fn smooth(&self, points: Vec<Vec2>) -> Vec<Vec2> {
+        let mut smoothed = Vec::new();
+        let mut anchor = 0;
+        while anchor + 1 < points.len() {
+            let mut next = anchor + 1;
+            while next + 1 < points.len() && self.clear_line(points[anchor], points[next + 1]) {
+                next += 1;
+            }
+            smoothed.push(points[next]);
+            anchor = next;
+        }
+        smoothed
+    }
+
+    /// Whether walking straight from `from` to `to` stays in open cells
+    pub fn clear_line(&self, from: Vec2, to: Vec2) -> bool {
+        let steps = ((to - from).length() / (self.cell_size / 4.0)).ceil() as usize;
+        (0..=steps).all(|step| {
+            let point = from.lerp(to, step as f32 / steps.max(1) as f32);
+            let cell = self.cell(point);
+            // The ends may have been snapped out of a wall
+            !self.walls[cell] || cell == self.cell(from) || cell == self.cell(to)
+        })
+    }
+
+    /// Open cells next to `cell` with the cost of stepping to them.
+    /// Diagonals are only taken when both sides are open so paths never clip a corner
+    fn neighbours(&self, cell: usize) -> impl Iterator<Item = (usize, u32)> + '_ {
+        let (x, y) = ((cell % self.width) as isize, (cell / self.width) as isize);
+        let open = move |dx: isize, dy: isize| {
+            let (x, y) = (x + dx, y + dy);
+            if x < 0 || y < 0 || x >= self.width as isize || y >= self.height as isize {
+                return None;
+            }
+            let next = y as usize * self.width + x as usize;
+            (!self.walls[next]).then(|| next)
+        };
+
+        [
+            (-1, 0),
+            (1, 0),
+            (0, -1),
+            (0, 1),
+            (-1, -1),
+            (1, -1),
+            (-1, 1),
+            (1, 1),
+        ]
+        .into_iter()
+        .filter_map(move |(dx, dy)| {
+            let next = open(dx, dy)?;
+            if dx != 0 && dy != 0 {
+                open(dx, 0)?;
+                open(0, dy)?;
+                Some((next, DIAGONAL_COST))
+            } else {
+                Some((next, STRAIGHT_COST))
+            }
+        })
+    }
+
+    /// `cell` if it's open, otherwise the closest open cell to it
+    fn nearest_open(&self, cell: usize) -> Option<usize> {
+        let mut seen = vec![false; self.walls.len()];
+        let mut open = VecDeque::from([cell]);
+        seen[cell] = true;
+        while let Some(cell) = open.pop_front() {
+            if !self.walls[cell] {
+                return Some(cell);
+            }
+            let (x, y) = (cell % self.width, cell / self.width);
+            let neighbours = [
+                (x > 0).then(|| cell - 1),
+                (x + 1 < self.width).then(|| cell + 1),
+                (y > 0).then(|| cell - self.width),
+                (y + 1 < self.height).then(|| cell + self.width),
+            ];
+            for next in neighbours.into_iter().flatten() {
+                if !seen[next] {
+                    seen[next] = true;
+                    open.push_back(next);
+                }
+            }
+        }
+        None
+    }
+}
+
+/// The waypoints a unit is following to its goal
+#[derive(Component, Default)]
+pub struct NavPath {
+    waypoints: VecDeque<Vec2>,
+    goal: Option<Vec2>,
+    age: f32,
+}
+
+impl NavPath {
+    /// Direction to walk this frame to get to `goal`, planning a new path when the goal moves.
+    /// Heads straight for the goal without a grid or when there's no way through
+    pub fn steer(
+        &mut self,
+        grid: Option<&NavGrid>,
+        position: Vec2,
+        goal: Vec2,
+        delta_seconds: f32,
+    ) -> Vec2 {
+        let grid = match grid {
+            Some(grid) => grid,
+            None => return (goal - position).try_normalize().unwrap_or_default(),
+        };
+
+        self.age += delta_seconds;
+        let goal_moved = self
+            .goal
+            .map(|old| old.distance(goal) > REPATH_DISTANCE)
+            .unwrap_or(true);
+        if goal_moved || self.age > REPATH_TIME || self.waypoints.is_empty() {
+            self.waypoints = grid
+                .find_path(position, goal)
+                .unwrap_or_else(|| vec![goal])
+                .into();
+            self.goal = Some(goal);
+            self.age = 0.0;
+        }
+
+        while self.waypoints.len() > 1 && self.waypoints[0].distance(position) < WAYPOINT_RADIUS {
+            self.waypoints.pop_front();
+        }
+        match self.waypoints.front() {
+            Some(waypoint) => (*waypoint - position).try_normalize().unwrap_or_default(),
+            None => Vec2::ZERO,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::Rect;
+
+    fn main_map() -> Map {
+        Map::parse(&std::fs::read("assets/main.map").unwrap(), "main").unwrap()
+    }
+
+    /// Every waypoint of `path`, starting from `from`, can be walked to from the one before it
+    fn assert_walkable(grid: &NavGrid, from: Vec2, path: &[Vec2]) {
+        let mut last = from;
+        for waypoint in path {
+            assert!(
+                grid.clear_line(last, *waypoint),
+                "{} to {} crosses a wall",
+                last,
+                waypoint
+            );
+            last = *waypoint;
+        }
+    }
+
+    #[test]
+    fn every_spawner_is_reachable_from_every_hero_spawn() {
+        let map = main_map();
+        let grid = NavGrid::bake(&map, NAV_CELL_SIZE, NAV_CLEARANCE);
+
+        for team in map.teams() {
+            let reached = grid.reachable(std::iter::once(map.hero_spawn(team)));
+            for (spawner, _) in &map.spawn_locations {
+                assert!(
+                    reached[grid.cell(*spawner)],
+                    "spawner at {} can't be reached from the spawn of team {}",
+                    spawner,
+                    team.0
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn path_goes_around_a_wall() {
+        let mut map = Map::new("wall");
+        let wall = Rect {
+            position: Vec2::ZERO,
+            size: Vec2::new(0.2, 1.0),
+            rotation: 0.0,
+        };
+        map.rects.push(wall);
+        map.player_spawn = Vec2::new(-1.0, 0.0);
+        map.enemy_spawn = Vec2::new(1.0, 0.0);
+        let grid = NavGrid::bake(&map, NAV_CELL_SIZE, NAV_CLEARANCE);
+
+        let from = map.player_spawn;
+        let path = grid.find_path(from, map.enemy_spawn).unwrap();
+        assert!(path.len() > 1, "path went straight through the wall");
+        assert_eq!(*path.last().unwrap(), map.enemy_spawn);
+
+        let mut last = from;
+        for waypoint in &path {
+            for step in 0..=20 {
+                let point = last.lerp(*waypoint, step as f32 / 20.0);
+                assert!(
+                    !wall.contains(point),
+                    "path passes through the wall at {}",
+                    point
+                );
+            }
+            last = *waypoint;
+        }
+    }
+
+    #[test]
+    fn smoothed_paths_stay_clear_of_walls() {
+        let map = main_map();
+        let grid = NavGrid::bake(&map, NAV_CELL_SIZE, NAV_CLEARANCE);
+
+        let from = map.player_spawn;
+        let path = grid.find_path(from, map.enemy_spawn).unwrap();
+        assert_walkable(&grid, from, &path);
+
+        for (spawner, _) in &map.spawn_locations {
+            let path = grid.find_path(from, *spawner).unwrap();
+            assert_walkable(&grid, from, &path);
+        }
+    }
+}
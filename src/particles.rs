@@ -1,3 +1,9 @@
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    reflect::TypeUuid,
+    utils::BoxedFuture,
+};
+use rand::Rng;
 use serde::Deserialize;
 
 use crate::prelude::*;
@@ -28,7 +34,9 @@ pub struct Particle {
 #[derive(Component)]
 pub struct ParticleSpawnerTimer(Timer);
 
-#[derive(Component, Deserialize)]
+/// Loaded from a `.particles` file
+#[derive(Component, Clone, Deserialize, TypeUuid)]
+#[uuid = "c47b1d2e-0a96-4f3d-8e5b-71f2a9d6c038"]
 pub struct ParticleSpawner {
     rate: f32,
     amount_per_burst: usize,
@@ -43,15 +51,19 @@ pub struct ParticlePlugin;
 
 impl Plugin for ParticlePlugin {
     fn build(&self, app: &mut App) {
-        app.add_system_set(
-            SystemSet::on_enter(GameState::MainMenu).with_system(spawn_menu_particles),
-        )
-        .add_system_set(SystemSet::on_exit(GameState::MainMenu).with_system(despawn_menu_particles))
-        .add_system(update_particle_lifetime)
-        .add_system(update_particle_size.after(emit_particles))
-        .add_system(update_particle_position.after(emit_particles))
-        .add_system(update_particle_color.after(emit_particles))
-        .add_system(emit_particles);
+        app.add_asset::<ParticleSpawner>()
+            .init_asset_loader::<ParticleSpawnerLoader>()
+            .add_system_set(
+                SystemSet::on_enter(GameState::MainMenu).with_system(spawn_menu_particles),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::MainMenu).with_system(despawn_menu_particles),
+            )
+            .add_system(update_particle_lifetime)
+            .add_system(update_particle_size.after(emit_particles))
+            .add_system(update_particle_position.after(emit_particles))
+            .add_system(update_particle_color.after(emit_particles))
+            .add_system(emit_particles);
     }
 }
 
@@ -74,7 +86,7 @@ fn lerp_color(a: Color, b: Color, t: f32) -> Color {
 
 fn update_particle_lifetime(
     mut particles: Query<(&mut Particle, &mut Visibility)>,
-    time: Res<Time>,
+    time: Res<GameTime>,
 ) {
     for (mut particle, mut visibility) in particles.iter_mut() {
         particle.lifetime.tick(time.delta());
@@ -99,7 +111,7 @@ fn update_particle_color(mut particles: Query<(&Particle, &ParticleColor, &mut S
 
 fn update_particle_position(
     mut particles: Query<(&Particle, &ParticleVelocity, &mut Transform)>,
-    time: Res<Time>,
+    time: Res<GameTime>,
 ) {
     for (particle, velocity, mut transform) in particles.iter_mut() {
         let velocity = lerp_vec2(velocity.start, velocity.end, particle.lifetime.percent());
@@ -107,7 +119,7 @@ fn update_particle_position(
     }
 }
 
-fn spawn_particle(commands: &mut Commands, spawner: &ParticleSpawner) -> Entity {
+fn spawn_particle(commands: &mut Commands, spawner: &ParticleSpawner, rng: &mut GameRng) -> Entity {
     let particle = commands
         .spawn()
         .insert(Particle {
@@ -118,8 +130,8 @@ fn spawn_particle(commands: &mut Commands, spawner: &ParticleSpawner) -> Entity
     let mut sprite = SpriteBundle::default();
     sprite.visibility.is_visible = false;
     sprite.transform.translation = Vec3::new(
-        spawner.position_variance * (2.0 * rand::random::<f32>() - 1.0),
-        spawner.position_variance * (2.0 * rand::random::<f32>() - 1.0),
+        spawner.position_variance * (2.0 * rng.gen::<f32>() - 1.0),
+        spawner.position_variance * (2.0 * rng.gen::<f32>() - 1.0),
         0.0,
     );
 
@@ -141,7 +153,8 @@ fn spawn_particle(commands: &mut Commands, spawner: &ParticleSpawner) -> Entity
 fn emit_particles(
     mut spawners: Query<(&Children, &ParticleSpawner, &mut ParticleSpawnerTimer)>,
     mut particles: Query<(&mut Particle, &mut Visibility, &mut Transform)>,
-    time: Res<Time>,
+    time: Res<GameTime>,
+    mut rng: ResMut<GameRng>,
 ) {
     for (children, spawner, mut timer) in spawners.iter_mut() {
         timer.0.tick(time.delta());
@@ -156,8 +169,8 @@ fn emit_particles(
                                 Timer::from_seconds(spawner.particle_lifetime, false);
                             visibility.is_visible = true;
                             transform.translation = Vec3::new(
-                                spawner.position_variance * (2.0 * rand::random::<f32>() - 1.0),
-                                spawner.position_variance * (2.0 * rand::random::<f32>() - 1.0),
+                                spawner.position_variance * (2.0 * rng.gen::<f32>() - 1.0),
+                                spawner.position_variance * (2.0 * rng.gen::<f32>() - 1.0),
                                 0.0,
                             );
                             break;
@@ -169,16 +182,25 @@ fn emit_particles(
     }
 }
 
-fn spawn_menu_particles(mut commands: Commands) {
-    let ron_str = include_str!("../assets/menu_particles.ron");
-    let spawner =
-        ron::from_str::<ParticleSpawner>(ron_str).expect("Failed to load basic_spawner.ron");
+fn spawn_menu_particles(
+    mut commands: Commands,
+    mut rng: ResMut<GameRng>,
+    our_assets: Res<OurAssets>,
+    spawners: Res<Assets<ParticleSpawner>>,
+) {
+    let spawner = match spawners.get(our_assets.menu_particles.clone()) {
+        Some(spawner) => spawner.clone(),
+        None => {
+            error!("Menu particles aren't loaded");
+            return;
+        }
+    };
 
     let mut particles = Vec::new();
     for _i in 0..((1.1 * spawner.particle_lifetime / spawner.rate).ceil() as usize
         * spawner.amount_per_burst)
     {
-        particles.push(spawn_particle(&mut commands, &spawner));
+        particles.push(spawn_particle(&mut commands, &spawner, &mut rng));
     }
 
     commands
@@ -198,3 +220,24 @@ fn despawn_menu_particles(mut commands: Commands, spawners: Query<Entity, With<P
         commands.entity(spawner).despawn_recursive();
     }
 }
+
+#[derive(Default)]
+pub struct ParticleSpawnerLoader;
+
+impl AssetLoader for ParticleSpawnerLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let spawner = ron::de::from_bytes::<ParticleSpawner>(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(spawner));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["particles"]
+    }
+}
@@ -0,0 +1,97 @@
+use bevy::render::camera::Camera;
+use heron::PhysicsTime;
+
+use crate::prelude::*;
+
+/// A choice made in the pause menu, carried out by `apply_pause_action` on the next frame
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PauseAction {
+    Resume,
+    Restart,
+    QuitToMenu,
+}
+
+pub struct PausePlugin;
+
+impl Plugin for PausePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(toggle_pause)
+            .add_system_set(SystemSet::on_enter(GameState::Paused).with_system(freeze_game))
+            .add_system_set(SystemSet::on_exit(GameState::Paused).with_system(unfreeze_game))
+            .add_system_set(
+                SystemSet::on_update(GameState::Paused).with_system(apply_pause_action),
+            );
+    }
+}
+
+/// Escape or Start pauses a match in progress and resumes a paused one
+fn toggle_pause(
+    keyboard: Res<Input<KeyCode>>,
+    buttons: Res<Input<GamepadButton>>,
+    mut state: ResMut<State<GameState>>,
+) {
+    let pressed = keyboard.just_pressed(KeyCode::Escape)
+        || (0..16)
+            .any(|id| buttons.just_pressed(GamepadButton(Gamepad(id), GamepadButtonType::Start)));
+    if !pressed {
+        return;
+    }
+
+    let result = match state.current() {
+        GameState::GamePlay => state.push(GameState::Paused),
+        GameState::Paused => state.pop(),
+        _ => return,
+    };
+    if let Err(e) = result {
+        error!("{:?}", e);
+    }
+}
+
+/// Gameplay systems stop with `GamePlay` inactive, this stops everything that runs regardless
+fn freeze_game(mut game_time: ResMut<GameTime>, mut physics_time: ResMut<PhysicsTime>) {
+    game_time.paused = true;
+    physics_time.pause();
+}
+
+fn unfreeze_game(mut game_time: ResMut<GameTime>, mut physics_time: ResMut<PhysicsTime>) {
+    game_time.paused = false;
+    physics_time.resume();
+}
+
+fn apply_pause_action(
+    mut commands: Commands,
+    action: Option<Res<PauseAction>>,
+    match_entities: Query<Entity, (Without<Parent>, Without<Camera>)>,
+    mut state: ResMut<State<GameState>>,
+) {
+    let action = match action {
+        Some(action) => *action,
+        None => return,
+    };
+    commands.remove_resource::<PauseAction>();
+
+    let result = match action {
+        PauseAction::Resume => state.pop(),
+        PauseAction::Restart => {
+            despawn_match(&mut commands, &match_entities);
+            state.replace(GameState::GamePlay)
+        }
+        PauseAction::QuitToMenu => {
+            despawn_match(&mut commands, &match_entities);
+            state.replace(GameState::MainMenu)
+        }
+    };
+    if let Err(e) = result {
+        error!("{:?}", e);
+    }
+}
+
+/// Everything a match spawns hangs off a root entity, only the cameras outlive it
+fn despawn_match(
+    commands: &mut Commands,
+    match_entities: &Query<Entity, (Without<Parent>, Without<Camera>)>,
+) {
+    for entity in match_entities.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
@@ -1,13 +1,28 @@
 use bevy::render::camera::Camera2d;
-use rand::Rng;
 
 use crate::{
-    assets::{BulletFrames, ChickenWalkFrames, Rotate},
+    ability::equip_abilities,
+    assets::{BulletFrames, UnitSprites},
+    bot::Bot,
+    economy::{Bank, HeroUpgrades, Income, LastAttacker},
+    events::{BulletFired, HeroRespawned, UnitDied},
     prelude::*,
+    rules::{MatchRules, MatchScore},
+    unit::{spawn_unit, UnitDef},
+    weapon::{equip_weapon, fire_weapon, Weapon},
 };
 
 pub struct PlayerPlugin;
 
+/// Every system that writes a `PlayerIntent` runs under this label,
+/// so movement and shooting see this frame's intent
+#[derive(SystemLabel, Clone, Hash, Debug, PartialEq, Eq)]
+pub struct PlayerInputLabel;
+
+/// Keyboard and gamepad input, other intent sources run after it to take priority
+#[derive(SystemLabel, Clone, Hash, Debug, PartialEq, Eq)]
+pub struct HumanInputLabel;
+
 #[derive(Component)]
 struct BulletParentTag;
 
@@ -16,9 +31,10 @@ impl Plugin for PlayerPlugin {
         app.add_system_set(SystemSet::on_enter(GameState::GamePlay).with_system(spawn_player))
             .add_system_set(
                 SystemSet::on_update(GameState::GamePlay)
-                    .with_system(player_movement)
+                    .with_system(player_input.label(PlayerInputLabel).label(HumanInputLabel))
+                    .with_system(player_movement.after(PlayerInputLabel))
                     .with_system(camera_follow.after(player_movement))
-                    .with_system(player_shoot)
+                    .with_system(player_shoot.after(PlayerInputLabel))
                     .with_system(player_death),
             );
     }
@@ -28,70 +44,181 @@ fn camera_follow(
     player_query: Query<&Transform, With<Player>>,
     mut camera_query: Query<&mut Transform, (With<Camera2d>, Without<Player>)>,
 ) {
-    let mut camera_translation = camera_query.single_mut();
-    let player_translation = player_query.single().translation;
+    let mut camera_translation = match camera_query.get_single_mut() {
+        Ok(camera) => camera,
+        //Headless matches have no camera to follow with
+        Err(_) => return,
+    };
+    //spawn_player reports a player that couldn't be spawned
+    let player_translation = match player_query.get_single() {
+        Ok(player) => player.translation,
+        Err(_) => return,
+    };
     camera_translation.translation.x = player_translation.x;
     camera_translation.translation.y = player_translation.y;
 }
 
-fn player_shoot(
-    mut commands: Commands,
-    mut player: Query<(&Transform, &mut Animation, &mut Player, &RespawnTimer)>,
-    parent: Query<Entity, With<BulletParentTag>>,
-
+/// Turns keyboard and gamepad state into the intent of any player that isn't driven by a bot
+fn player_input(
+    mut player: Query<&mut PlayerIntent, (With<Player>, Without<Bot>)>,
     keyboard: Res<Input<KeyCode>>,
     axis: Res<Axis<GamepadAxis>>,
-    time: Res<Time>,
-
-    bullets: Res<BulletFrames>,
+    buttons: Res<Input<GamepadButton>>,
 ) {
-    let parent = parent.single();
-    let (transform, mut animation, mut player, respawn) = player.single_mut();
-    if respawn.is_dead {
-        animation.flip_y = true;
-        animation.playing = false;
-        return;
-    }
-    animation.flip_y = false;
-
-    if !player.bullet_cooldown.finished() {
-        player.bullet_cooldown.tick(time.delta());
-        return;
-    }
+    let mut intent = match player.get_single_mut() {
+        Ok(intent) => intent,
+        Err(_) => return,
+    };
 
-    let mut target_dir = Vec2::ZERO;
+    intent.movement = Vec2::ZERO;
+    intent.aim = Vec2::ZERO;
+    intent.command = None;
+    intent.purchase = None;
+    intent.ability = None;
 
     for id in 0..16 {
-        let axis_lx = GamepadAxis(Gamepad(id), GamepadAxisType::RightStickX);
-        let axis_ly = GamepadAxis(Gamepad(id), GamepadAxisType::RightStickY);
-
+        let axis_lx = GamepadAxis(Gamepad(id), GamepadAxisType::LeftStickX);
+        let axis_ly = GamepadAxis(Gamepad(id), GamepadAxisType::LeftStickY);
         if let (Some(x), Some(y)) = (axis.get(axis_lx), axis.get(axis_ly)) {
-            target_dir = Vec2::new(x, y);
+            intent.movement += Vec2::new(x, y);
+        }
+
+        let axis_rx = GamepadAxis(Gamepad(id), GamepadAxisType::RightStickX);
+        let axis_ry = GamepadAxis(Gamepad(id), GamepadAxisType::RightStickY);
+        if let (Some(x), Some(y)) = (axis.get(axis_rx), axis.get(axis_ry)) {
+            intent.aim = Vec2::new(x, y);
+        }
+
+        let pressed = |button| buttons.just_pressed(GamepadButton(Gamepad(id), button));
+        if pressed(GamepadButtonType::DPadUp) {
+            intent.command = Some(SquadCommand::Rally);
+        } else if pressed(GamepadButtonType::DPadRight) {
+            intent.command = Some(SquadCommand::AttackSpawner);
+        } else if pressed(GamepadButtonType::DPadDown) {
+            intent.command = Some(SquadCommand::Hold);
+        } else if pressed(GamepadButtonType::DPadLeft) {
+            intent.command = Some(SquadCommand::Release);
         }
+        if pressed(GamepadButtonType::North) {
+            intent.purchase = Some(Purchase::SpawnerUpgrade);
+        } else if pressed(GamepadButtonType::West) {
+            intent.purchase = Some(Purchase::HeroUpgrade);
+        } else if pressed(GamepadButtonType::East) {
+            intent.purchase = Some(Purchase::MinionWave);
+        }
+        if pressed(GamepadButtonType::South) {
+            intent.ability = Some(0);
+        } else if pressed(GamepadButtonType::RightTrigger) {
+            intent.ability = Some(1);
+        } else if pressed(GamepadButtonType::LeftTrigger) {
+            intent.ability = Some(2);
+        }
+    }
+
+    if keyboard.pressed(KeyCode::D) {
+        intent.movement.x += 1.0;
+    }
+    if keyboard.pressed(KeyCode::A) {
+        intent.movement.x -= 1.0;
+    }
+    if keyboard.pressed(KeyCode::W) {
+        intent.movement.y += 1.0;
+    }
+    if keyboard.pressed(KeyCode::S) {
+        intent.movement.y -= 1.0;
     }
 
     if keyboard.pressed(KeyCode::Left) {
-        target_dir.x -= 1.0;
+        intent.aim.x -= 1.0;
     }
     if keyboard.pressed(KeyCode::Right) {
-        target_dir.x += 1.0;
+        intent.aim.x += 1.0;
     }
     if keyboard.pressed(KeyCode::Up) {
-        target_dir.y += 1.0;
+        intent.aim.y += 1.0;
     }
     if keyboard.pressed(KeyCode::Down) {
-        target_dir.y -= 1.0;
+        intent.aim.y -= 1.0;
+    }
+
+    if keyboard.just_pressed(KeyCode::Key1) {
+        intent.command = Some(SquadCommand::Rally);
+    } else if keyboard.just_pressed(KeyCode::Key2) {
+        intent.command = Some(SquadCommand::AttackSpawner);
+    } else if keyboard.just_pressed(KeyCode::Key3) {
+        intent.command = Some(SquadCommand::Hold);
+    } else if keyboard.just_pressed(KeyCode::Key4) {
+        intent.command = Some(SquadCommand::Release);
+    }
+
+    if keyboard.just_pressed(KeyCode::E) {
+        intent.purchase = Some(Purchase::SpawnerUpgrade);
+    } else if keyboard.just_pressed(KeyCode::Q) {
+        intent.purchase = Some(Purchase::HeroUpgrade);
+    } else if keyboard.just_pressed(KeyCode::R) {
+        intent.purchase = Some(Purchase::MinionWave);
+    }
+
+    if keyboard.just_pressed(KeyCode::Space) {
+        intent.ability = Some(0);
+    } else if keyboard.just_pressed(KeyCode::F) {
+        intent.ability = Some(1);
+    } else if keyboard.just_pressed(KeyCode::C) {
+        intent.ability = Some(2);
+    }
+}
+
+fn player_shoot(
+    mut commands: Commands,
+    mut player: Query<
+        (
+            &Transform,
+            &mut Animation,
+            &mut RangedAttack,
+            &Weapon,
+            &PlayerIntent,
+            &RespawnTimer,
+        ),
+        (With<Player>, Without<Stunned>),
+    >,
+    parent: Query<Entity, With<BulletParentTag>>,
+
+    time: Res<GameTime>,
+    mut rng: ResMut<GameRng>,
+    mut fired: EventWriter<BulletFired>,
+
+    bullets: Option<Res<BulletFrames>>,
+) {
+    let parent = match parent.get_single() {
+        Ok(parent) => parent,
+        Err(_) => return,
+    };
+    let (transform, mut animation, mut attack, weapon, intent, respawn) =
+        match player.get_single_mut() {
+            Ok(player) => player,
+            Err(_) => return,
+        };
+    if respawn.is_dead {
+        animation.flip_y = true;
+        animation.playing = false;
+        return;
+    }
+    animation.flip_y = false;
+
+    if !attack.cooldown.finished() {
+        attack.cooldown.tick(time.delta());
+        return;
     }
 
+    let mut target_dir = intent.aim;
+
     if target_dir.length() > 0.1 {
         target_dir = target_dir.normalize();
 
         let mut transform = *transform;
         transform.translation.z -= 10.0;
 
-        let size = 0.1;
-
-        player.bullet_cooldown.tick(time.delta());
+        attack.cooldown.tick(time.delta());
 
         animation.current_frame = 0;
         animation.playing_alt = true;
@@ -103,35 +230,18 @@ fn player_shoot(
             transform.translation.x += 0.08;
         }
 
-        let num = rand::thread_rng().gen_range(0..2);
-        let sprite = bullets.frames[num].clone();
-
-        let bullet = commands
-            .spawn_bundle(SpriteSheetBundle {
-                sprite,
-                texture_atlas: bullets.texture.clone(),
-                transform,
-                ..default()
-            })
-            .insert(Bullet {
-                speed: 0.2,
-                direction: target_dir,
-            })
-            .insert(Rotate)
-            .insert(ChickenOrDog::Chicken)
-            .insert(RigidBody::Sensor)
-            .insert(CollisionShape::Sphere { radius: size / 2.0 })
-            .insert(RotationConstraints::lock())
-            .insert(
-                CollisionLayers::all_masks::<Layer>()
-                    .with_group(Layer::Bullet)
-                    .without_mask(Layer::Bullet)
-                    .without_mask(Layer::Player),
-            )
-            .insert(Collisions::default())
-            .insert(Name::new("Player Bullet"))
-            .id();
-        commands.entity(parent).add_child(bullet);
+        let shot = fire_weapon(
+            &mut commands,
+            &mut fired,
+            &mut rng,
+            transform,
+            Team::PLAYER,
+            &weapon.0,
+            &attack,
+            target_dir,
+            bullets.as_deref(),
+        );
+        commands.entity(parent).push_children(&shot);
     }
 }
 
@@ -141,105 +251,89 @@ fn player_movement(
             &mut Transform,
             &mut Animation,
             &MovementStats,
+            &PlayerIntent,
             &RespawnTimer,
         ),
-        With<Player>,
+        (With<Player>, Without<Stunned>),
     >,
-    time: Res<Time>,
-    keyboard: Res<Input<KeyCode>>,
-    axis: Res<Axis<GamepadAxis>>,
+    time: Res<GameTime>,
 ) {
-    let (mut transform, mut animation, stats, respawn) = player.single_mut();
+    let (mut transform, mut animation, stats, intent, respawn) = match player.get_single_mut() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
     if respawn.is_dead {
         return;
     }
 
-    animation.playing = false;
-    for id in 0..16 {
-        let axis_lx = GamepadAxis(Gamepad(id), GamepadAxisType::LeftStickX);
-        let axis_ly = GamepadAxis(Gamepad(id), GamepadAxisType::LeftStickY);
-
-        if let (Some(x), Some(y)) = (axis.get(axis_lx), axis.get(axis_ly)) {
-            if x.abs() > 0.01 || y.abs() > 0.01 {
-                animation.playing = true;
-            }
-            transform.translation.x += x * stats.speed * time.delta_seconds();
-            transform.translation.y += y * stats.speed * time.delta_seconds();
-        }
-    }
+    transform.translation += (intent.movement * stats.speed * time.delta_seconds()).extend(0.0);
 
-    if keyboard.pressed(KeyCode::D) {
-        transform.translation.x += time.delta_seconds() * stats.speed;
-        animation.playing = true;
-        if !animation.playing_alt {
+    animation.playing = intent.movement.length() > 0.01;
+    if !animation.playing_alt {
+        if intent.movement.x > 0.0 {
             animation.flip_x = true;
-        }
-    }
-    if keyboard.pressed(KeyCode::A) {
-        transform.translation.x -= time.delta_seconds() * stats.speed;
-        animation.playing = true;
-        if !animation.playing_alt {
+        } else if intent.movement.x < 0.0 {
             animation.flip_x = false;
         }
     }
-    if keyboard.pressed(KeyCode::W) {
-        transform.translation.y += time.delta_seconds() * stats.speed;
-        if !animation.playing_alt {
-            animation.playing = true;
-        }
-    }
-    if keyboard.pressed(KeyCode::S) {
-        transform.translation.y -= time.delta_seconds() * stats.speed;
-        if !animation.playing_alt {
-            animation.playing = true;
-        }
-    }
 }
 
 fn spawn_player(
     mut commands: Commands,
-    chicken_walk: Res<ChickenWalkFrames>,
+    units: Res<Assets<UnitDef>>,
+    factions: Res<Factions>,
+    sprites: Option<Res<UnitSprites>>,
     map: Res<Assets<Map>>,
-    our_assets: Res<OurAssets>,
+    current_map: Res<CurrentMap>,
+    tuning: Res<GameTuning>,
 ) {
-    let size = chicken_walk.frames[0].custom_size.unwrap().x;
-    let map = map.get(our_assets.map.clone()).unwrap();
+    //create_map reports a missing map
+    let map = match map.get(current_map.0.clone()) {
+        Some(map) => map,
+        None => return,
+    };
+    let handle = match factions.get(Team::PLAYER) {
+        Some(faction) => &faction.hero,
+        None => {
+            error!("There is no faction for the player");
+            return;
+        }
+    };
+    let def = match units.get(handle) {
+        Some(def) => def,
+        None => {
+            error!("The player's hero isn't loaded");
+            return;
+        }
+    };
+    let frames = sprites.as_ref().and_then(|sprites| sprites.get(handle));
 
+    let player = spawn_unit(
+        &mut commands,
+        def,
+        frames,
+        Team::PLAYER,
+        map.hero_spawn(Team::PLAYER).extend(800.0),
+    );
     commands
-        .spawn_bundle(SpriteSheetBundle {
-            sprite: chicken_walk.frames[0].clone(),
-            texture_atlas: chicken_walk.texture.clone(),
-            transform: Transform::from_translation(map.player_spawn.extend(800.0)),
-            ..default()
-        })
-        .insert(Player {
-            bullet_cooldown: Timer::from_seconds(0.35, true),
-        })
-        .insert(MovementStats { speed: 0.5 })
-        .insert(RigidBody::Dynamic)
-        .insert(CollisionShape::Sphere { radius: size / 2.0 })
-        .insert(RotationConstraints::lock())
-        .insert(CollisionLayers::all_masks::<Layer>().with_group(Layer::Player))
-        .insert(DamageFlash {
-            timer: Timer::from_seconds(0.0, false),
-        })
-        .insert(Animation {
-            current_frame: 0,
-            frames: chicken_walk.frames.iter().map(|f| f.index).collect(),
-            alt_frames: Some(chicken_walk.alt_frames.iter().map(|f| f.index).collect()),
-            playing_alt: false,
-            playing: false,
-            flip_x: false,
-            flip_y: false,
-            timer: Timer::from_seconds(1.0 / 10.0, true),
-        })
-        .insert(Name::new("Player"))
-        .insert(ChickenOrDog::Chicken)
+        .entity(player)
+        .insert(Player)
+        .insert(PlayerIntent::default())
+        .insert(HeroUpgrades::default())
         .insert(RespawnTimer {
             is_dead: false,
             timer: Timer::from_seconds(0.0, false),
-        })
-        .insert(Health(PLAYER_HP));
+        });
+    if let Some(name) = &def.weapon {
+        match tuning.weapons.get(name) {
+            Some(weapon) => equip_weapon(&mut commands, player, weapon),
+            None => error!(
+                "{} has a weapon called {} that doesn't exist",
+                def.name, name
+            ),
+        }
+    }
+    equip_abilities(&mut commands, player, def, &tuning.abilities);
 
     commands
         .spawn_bundle(TransformBundle::default())
@@ -250,27 +344,53 @@ fn spawn_player(
 fn player_death(
     mut players: Query<
         (
+            Entity,
             &mut Transform,
             &mut Health,
-            &ChickenOrDog,
+            &MaxHealth,
+            &Team,
             &mut RespawnTimer,
+            Option<&LastAttacker>,
         ),
         Or<(With<Player>, With<Enemy>)>,
     >,
-    spawners: Query<(&GlobalTransform, &ChickenOrDog), With<Spawner>>,
-    time: Res<Time>,
+    spawners: Query<(&GlobalTransform, &Team), With<Spawner>>,
+    time: Res<GameTime>,
+    tuning: Res<GameTuning>,
     map: Res<Assets<Map>>,
-    our_assets: Res<OurAssets>,
+    current_map: Res<CurrentMap>,
+    mut bank: Bank,
+    rules: Res<MatchRules>,
+    mut score: ResMut<MatchScore>,
+    mut died: EventWriter<UnitDied>,
+    mut respawned: EventWriter<HeroRespawned>,
 ) {
-    for (mut transform, mut health, team, mut respawn) in players.iter_mut() {
+    for (hero, mut transform, mut health, max_health, team, mut respawn, last_attacker) in
+        players.iter_mut()
+    {
         if health.0 <= 0.0 && !respawn.is_dead {
-            respawn.timer = Timer::from_seconds(2.0, false);
+            let killer = last_attacker.and_then(|attacker| attacker.0);
+            if let Some(killer) = killer {
+                bank.earn(killer, tuning.economy.hero_bounty, Income::HeroKill);
+                *score.kills.entry(killer).or_default() += 1;
+            }
+            died.send(UnitDied {
+                unit: hero,
+                team: *team,
+                killer,
+                hero: true,
+            });
+            respawn.timer = Timer::from_seconds(tuning.respawn_time, false);
             respawn.is_dead = true;
-            health.0 = PLAYER_HP;
+            health.0 = max_health.0;
+        }
+        // Under sudden death the dead stay dead
+        if !rules.respawns() {
+            continue;
         }
         respawn.timer.tick(time.delta());
         if respawn.timer.just_finished() {
-            health.0 = PLAYER_HP;
+            health.0 = max_health.0;
             respawn.is_dead = false;
             let friendly_spawners = spawners
                 .iter()
@@ -286,16 +406,19 @@ fn player_death(
             let respawn_location =
                 find_farthest(transform.translation.truncate(), friendly_spawners).unwrap_or_else(
                     || {
-                        let map = map.get(our_assets.map.clone()).unwrap();
-                        match team {
-                            ChickenOrDog::Chicken => map.player_spawn,
-                            ChickenOrDog::Dog => map.enemy_spawn,
-                        }
+                        map.get(current_map.0.clone())
+                            .map(|map| map.hero_spawn(*team))
+                            .unwrap_or_default()
                     },
                 );
 
             let z = transform.translation.z;
             transform.translation = respawn_location.extend(z);
+            respawned.send(HeroRespawned {
+                hero,
+                team: *team,
+                position: respawn_location,
+            });
         }
     }
 }
@@ -5,27 +5,47 @@ pub use heron::prelude::*;
 
 use bevy::utils::Duration;
 use bevy_inspector_egui::Inspectable;
+use heron::rapier_plugin::PhysicsWorld;
 use serde::{Deserialize, Serialize};
 
-pub use crate::{assets::OurAssets, map::Map, GameState};
-
-pub const PLAYER_HP: f32 = 10.0;
-pub const MINION_MELEE_DMG: f32 = 0.5;
-pub const MINION_MELEE_COOLDOWN: f32 = 0.75;
-pub const MINION_MELEE_RANGE: f32 = 0.25;
+pub use crate::{
+    assets::OurAssets,
+    bullet::{Falloff, WallBehavior},
+    difficulty::Difficulty,
+    economy::Purchase,
+    faction::Factions,
+    game_time::GameTime,
+    map::{CurrentMap, Map},
+    rng::GameRng,
+    squad::SquadCommand,
+    tuning::GameTuning,
+    GameState,
+};
 
 #[derive(Component, Reflect, Default)]
 #[reflect(Component)]
-pub struct Player {
-    pub bullet_cooldown: Timer,
+pub struct Player;
+
+/// What the player wants to do this frame, written by keyboard/gamepad input or a bot
+#[derive(Component, Reflect, Default, Debug)]
+#[reflect(Component)]
+pub struct PlayerIntent {
+    pub movement: Vec2,
+    pub aim: Vec2,
+    /// Order given to the player's minions this frame
+    #[reflect(ignore)]
+    pub command: Option<SquadCommand>,
+    /// What the player is buying this frame
+    #[reflect(ignore)]
+    pub purchase: Option<Purchase>,
+    /// Ability slot the player uses this frame
+    #[reflect(ignore)]
+    pub ability: Option<usize>,
 }
 
 #[derive(Component, Reflect, Default)]
 #[reflect(Component)]
-pub struct Enemy {
-    pub range: f32,
-    pub bullet_cooldown: Timer,
-}
+pub struct Enemy;
 
 #[derive(Component, Reflect, Debug, Default)]
 #[reflect(Component)]
@@ -40,11 +60,21 @@ pub struct DamageFlash {
     pub timer: Timer,
 }
 
+/// A unit can't move, shoot or use abilities until this runs out
+#[derive(Component, Reflect, Debug, Default)]
+#[reflect(Component)]
+pub struct Stunned(pub Timer);
+
 #[derive(Component, Debug, Reflect, Default)]
 #[reflect(Component)]
 pub struct Health(pub f32);
 
-#[derive(PhysicsLayer, Copy, Clone)]
+/// The health a unit spawns and respawns with
+#[derive(Component, Debug, Reflect, Default)]
+#[reflect(Component)]
+pub struct MaxHealth(pub f32);
+
+#[derive(PhysicsLayer, Copy, Clone, Debug, Deserialize)]
 pub enum Layer {
     Bullet,
     Enemy,
@@ -66,6 +96,8 @@ pub struct Animation {
     pub playing: bool,
     pub flip_x: bool,
     pub flip_y: bool,
+    /// The frames are drawn facing right rather than left
+    pub faces_right: bool,
     pub timer: Timer,
 }
 
@@ -75,11 +107,34 @@ pub struct MovementStats {
     pub speed: f32,
 }
 
+/// How fast a unit has been moving, measured from where it ends up each frame
+/// since units are moved by their transform rather than physics
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct ObservedVelocity {
+    pub velocity: Vec2,
+    pub last_position: Option<Vec2>,
+}
+
 #[derive(Component, Reflect, Default)]
 #[reflect(Component)]
 pub struct Bullet {
     pub speed: f32,
+    pub damage: f32,
     pub direction: Vec2,
+    /// Units it can still go through
+    pub pierce: u32,
+    /// Units it has already hurt
+    #[reflect(ignore)]
+    pub hits: Vec<Entity>,
+    /// How far it has flown and how far it can fly
+    pub traveled: f32,
+    pub range: f32,
+    pub lifetime: Timer,
+    #[reflect(ignore)]
+    pub falloff: Option<Falloff>,
+    #[reflect(ignore)]
+    pub walls: WallBehavior,
 }
 
 #[derive(Component, Reflect, Default)]
@@ -92,33 +147,79 @@ pub struct CircleCollider;
 
 #[derive(Component, Reflect, Default)]
 #[reflect(Component)]
-pub struct Minion {
-    pub attack_cooldown: Timer,
+pub struct Minion;
+
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct MeleeAttack {
+    pub damage: f32,
+    pub range: f32,
+    pub cooldown: Timer,
 }
 
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct RangedAttack {
+    pub damage: f32,
+    pub range: f32,
+    pub bullet_speed: f32,
+    pub cooldown: Timer,
+}
+
+/// Restores the health of nearby allies
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct Healer {
+    pub amount: f32,
+    pub range: f32,
+    pub cooldown: Timer,
+}
+
+/// How many minions a minion counts as when standing on a spawner
+#[derive(Component, Reflect, Default, Clone, Copy)]
+#[reflect(Component)]
+pub struct CaptureCount(pub u32);
+
+/// Which faction an entity fights for, an index into `Factions`
 #[derive(
-    Copy, Clone, Debug, Component, PartialEq, Eq, Serialize, Deserialize, Reflect, Inspectable,
+    Copy,
+    Clone,
+    Debug,
+    Default,
+    Component,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Serialize,
+    Deserialize,
+    Reflect,
+    Inspectable,
 )]
+#[serde(transparent)]
 #[reflect(Component)]
-pub enum ChickenOrDog {
-    Chicken,
-    Dog,
-}
+pub struct Team(pub u8);
 
-impl Default for ChickenOrDog {
-    fn default() -> Self {
-        ChickenOrDog::Chicken
-    }
+impl Team {
+    /// The faction the human player controls, every other faction is played by `Enemy` heroes
+    pub const PLAYER: Team = Team(0);
 }
 
 #[derive(Component, Reflect)]
 #[reflect(Component)]
 pub struct Spawner {
     pub spawn_timer: Timer,
-    pub capture_time: f32,
-    // -1.0 < progress < 1.0
-    // negative means the enemy won the objective
+    // 0.0 < progress < 1.0
+    // progress of `capturing` toward owning the objective
     pub capture_progress: f32,
+    pub capturing: Option<Team>,
+    /// The team outweighing the owner on the spawner, `None` while nobody is taking it
+    pub contested_by: Option<Team>,
+    /// Upgrade tier in `GameTuning::spawner_tiers`, lost when the spawner goes neutral
+    pub level: usize,
+    /// Minions bought in a wave, made one a frame on top of the usual ones
+    pub queued: u32,
 }
 
 impl Default for Spawner {
@@ -126,7 +227,10 @@ impl Default for Spawner {
         Self {
             spawn_timer: Timer::new(Duration::from_secs_f32(5.0), true),
             capture_progress: 0.0,
-            capture_time: 5.0,
+            capturing: None,
+            contested_by: None,
+            level: 0,
+            queued: 0,
         }
     }
 }
@@ -156,6 +260,47 @@ pub fn is_layer_collision(event: &CollisionEvent, layer: Layer) -> Option<(Entit
     }
 }
 
+/// Casts a ray between the two points and checks that no wall blocks it
+pub fn has_line_of_sight(physics_world: &PhysicsWorld, from: Vec2, to: Vec2) -> bool {
+    physics_world
+        .ray_cast_with_filter(
+            from.extend(0.0),
+            (to - from).extend(0.0),
+            false,
+            CollisionLayers::none()
+                .with_group(Layer::Wall)
+                .with_mask(Layer::Wall),
+            |_ent| true,
+        )
+        .is_none()
+}
+
+/// Where to aim from `from` so a bullet flying at `bullet_speed` meets a target at `target`
+/// moving at `velocity`, or `None` when the bullet can't catch it
+pub fn lead_target(from: Vec2, target: Vec2, velocity: Vec2, bullet_speed: f32) -> Option<Vec2> {
+    // Solve |offset + velocity * t| = bullet_speed * t for the earliest t > 0
+    let offset = target - from;
+    let a = velocity.length_squared() - bullet_speed * bullet_speed;
+    let b = 2.0 * offset.dot(velocity);
+    let c = offset.length_squared();
+
+    let time = if a.abs() < f32::EPSILON {
+        // The target moves as fast as the bullet
+        (b < 0.0).then(|| -c / b)
+    } else {
+        let discriminant = b * b - 4.0 * a * c;
+        if discriminant < 0.0 {
+            return None;
+        }
+        let root = discriminant.sqrt();
+        [(-b - root) / (2.0 * a), (-b + root) / (2.0 * a)]
+            .into_iter()
+            .filter(|time| *time > 0.0)
+            .reduce(f32::min)
+    }?;
+    Some(target + velocity * time)
+}
+
 pub fn find_closest(position: Vec2, iter: impl Iterator<Item = GlobalTransform>) -> Option<Vec2> {
     iter.min_by(|transform, other_transform| {
         (position - transform.translation.truncate())
@@ -0,0 +1,273 @@
+use std::fs;
+
+use bevy::utils::Duration;
+use heron::PhysicsSteps;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    game_time::UpdateGameTimeLabel,
+    player::{HumanInputLabel, PlayerInputLabel},
+    prelude::*,
+    rules::entered_game_over,
+};
+
+/// Everything needed to re-simulate a match: the map, the RNG seed, the delta of every frame,
+/// the difficulty and the player's intent and commands on every frame
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Replay {
+    pub map: String,
+    pub seed: u64,
+    pub timestep: Duration,
+    /// Delta of each gameplay frame for matches recorded at the real frame rate,
+    /// empty when every frame took `timestep`
+    #[serde(default)]
+    pub deltas: Vec<Duration>,
+    #[serde(default)]
+    pub difficulty: Difficulty,
+    /// (movement, aim) of the player for each gameplay frame
+    pub frames: Vec<(Vec2, Vec2)>,
+    /// Squad commands the player gave, by the frame they were given on
+    #[serde(default)]
+    pub commands: Vec<(usize, SquadCommand)>,
+    /// What the player bought, by the frame they bought it on
+    #[serde(default)]
+    pub purchases: Vec<(usize, Purchase)>,
+    pub outcome: Option<ReplayOutcome>,
+}
+
+/// The state a match ended in, compared after playback to check the replay stayed in sync
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReplayOutcome {
+    pub won: bool,
+    pub capture_progress: Vec<f32>,
+    pub hero_health: Vec<f32>,
+}
+
+impl ReplayOutcome {
+    pub fn new<'a>(
+        won: bool,
+        spawners: impl Iterator<Item = &'a Spawner>,
+        hero_health: impl Iterator<Item = &'a Health>,
+    ) -> Self {
+        Self {
+            won,
+            capture_progress: spawners.map(|spawner| spawner.capture_progress).collect(),
+            hero_health: hero_health.map(|health| health.0).collect(),
+        }
+    }
+
+    /// Reads the outcome of a finished match out of the world
+    pub fn from_world(world: &mut World, won: bool) -> Self {
+        let mut spawners = world.query::<&Spawner>();
+        let mut heroes = world.query_filtered::<&Health, Or<(With<Player>, With<Enemy>)>>();
+        Self::new(won, spawners.iter(world), heroes.iter(world))
+    }
+}
+
+impl Replay {
+    pub fn load(path: &str) -> anyhow::Result<Self> {
+        let contents =
+            fs::read_to_string(path).with_context(|| format!("failed to read replay {}", path))?;
+        ron::de::from_str(&contents).with_context(|| format!("failed to parse replay {}", path))
+    }
+
+    pub fn save(&self, path: &str) -> anyhow::Result<()> {
+        let contents = ron::to_string(self).context("failed to serialize replay")?;
+        fs::write(path, contents).with_context(|| format!("failed to write replay {}", path))
+    }
+}
+
+/// Records the match to a file, or plays a recorded one back in place of the player's input.
+/// Gameplay and physics step by the replay's timestep, or by each recorded frame's delta
+pub enum ReplayPlugin {
+    Record {
+        path: String,
+        map: String,
+        /// `None` keeps the game running in real time and records every frame's delta instead
+        timestep: Option<Duration>,
+    },
+    Playback(Replay),
+}
+
+struct ReplayRecorder {
+    path: String,
+    replay: Replay,
+}
+
+struct ReplayPlayback {
+    replay: Replay,
+    frame: usize,
+}
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        match self {
+            ReplayPlugin::Record {
+                path,
+                map,
+                timestep,
+            } => {
+                match timestep {
+                    Some(timestep) => {
+                        app.insert_resource(GameTime::fixed(*timestep))
+                            .insert_resource(PhysicsSteps::every_frame(*timestep));
+                    }
+                    None => {
+                        app.add_system_to_stage(
+                            CoreStage::First,
+                            step_physics_with_frame.after(UpdateGameTimeLabel),
+                        );
+                    }
+                }
+                app.insert_resource(ReplayRecorder {
+                    path: path.clone(),
+                    replay: Replay {
+                        map: map.clone(),
+                        timestep: timestep.unwrap_or_default(),
+                        ..default()
+                    },
+                })
+                .add_system_set(
+                    SystemSet::on_enter(GameState::GamePlay).with_system(start_recording),
+                )
+                .add_system_set(
+                    SystemSet::on_update(GameState::GamePlay)
+                        .with_system(record_intent.after(PlayerInputLabel)),
+                )
+                .add_system_set(
+                    SystemSet::new()
+                        .with_run_criteria(entered_game_over)
+                        .with_system(finish_recording),
+                );
+            }
+            ReplayPlugin::Playback(replay) => {
+                app.insert_resource(GameTime::fixed(replay.timestep))
+                    .insert_resource(PhysicsSteps::every_frame(replay.timestep))
+                    .insert_resource(replay.difficulty.clone())
+                    .insert_resource(ReplayPlayback {
+                        replay: replay.clone(),
+                        frame: 0,
+                    })
+                    .add_system_to_stage(
+                        CoreStage::First,
+                        play_frame_time.before(UpdateGameTimeLabel),
+                    )
+                    .add_system_set(
+                        SystemSet::on_enter(GameState::GamePlay).with_system(start_playback),
+                    )
+                    .add_system_set(
+                        SystemSet::on_update(GameState::GamePlay).with_system(
+                            play_intent.label(PlayerInputLabel).after(HumanInputLabel),
+                        ),
+                    );
+            }
+        }
+    }
+}
+
+fn start_recording(
+    mut recorder: ResMut<ReplayRecorder>,
+    mut rng: ResMut<GameRng>,
+    difficulty: Res<Difficulty>,
+) {
+    let seed = rand::random();
+    rng.reseed(seed);
+    recorder.replay.seed = seed;
+    recorder.replay.difficulty = difficulty.clone();
+    recorder.replay.frames.clear();
+    recorder.replay.deltas.clear();
+    recorder.replay.commands.clear();
+    recorder.replay.purchases.clear();
+    recorder.replay.outcome = None;
+}
+
+fn record_intent(
+    mut recorder: ResMut<ReplayRecorder>,
+    player: Query<&PlayerIntent, With<Player>>,
+    time: Res<GameTime>,
+) {
+    if let Ok(intent) = player.get_single() {
+        if let Some(command) = intent.command {
+            let frame = recorder.replay.frames.len();
+            recorder.replay.commands.push((frame, command));
+        }
+        if let Some(purchase) = intent.purchase {
+            let frame = recorder.replay.frames.len();
+            recorder.replay.purchases.push((frame, purchase));
+        }
+        recorder.replay.frames.push((intent.movement, intent.aim));
+        if time.fixed_step.is_none() {
+            recorder.replay.deltas.push(time.delta());
+        }
+    }
+}
+
+/// Physics takes the same step as gameplay each frame, so a real time recording can be re-simulated
+fn step_physics_with_frame(time: Res<GameTime>, mut steps: ResMut<PhysicsSteps>) {
+    *steps = PhysicsSteps::every_frame(time.delta());
+}
+
+/// Steps gameplay and physics by the delta the frame being played back was recorded with
+fn play_frame_time(
+    playback: Res<ReplayPlayback>,
+    mut time: ResMut<GameTime>,
+    mut steps: ResMut<PhysicsSteps>,
+) {
+    if let Some(delta) = playback.replay.deltas.get(playback.frame) {
+        time.fixed_step = Some(*delta);
+        *steps = PhysicsSteps::every_frame(*delta);
+    }
+}
+
+fn finish_recording(
+    mut recorder: ResMut<ReplayRecorder>,
+    state: Res<State<GameState>>,
+    spawners: Query<&Spawner>,
+    heroes: Query<&Health, Or<(With<Player>, With<Enemy>)>>,
+) {
+    let won = matches!(state.current(), GameState::GameOver(result) if result.won());
+    recorder.replay.outcome = Some(ReplayOutcome::new(won, spawners.iter(), heroes.iter()));
+
+    if let Err(e) = recorder.replay.save(&recorder.path) {
+        error!("{:?}", e);
+    } else {
+        info!("Saved replay to {}", recorder.path);
+    }
+}
+
+fn start_playback(mut playback: ResMut<ReplayPlayback>, mut rng: ResMut<GameRng>) {
+    rng.reseed(playback.replay.seed);
+    playback.frame = 0;
+}
+
+fn play_intent(
+    mut playback: ResMut<ReplayPlayback>,
+    mut player: Query<&mut PlayerIntent, With<Player>>,
+) {
+    let mut intent = match player.get_single_mut() {
+        Ok(intent) => intent,
+        Err(_) => return,
+    };
+
+    let (movement, aim) = playback
+        .replay
+        .frames
+        .get(playback.frame)
+        .copied()
+        .unwrap_or_default();
+    intent.movement = movement;
+    intent.aim = aim;
+    intent.command = playback
+        .replay
+        .commands
+        .iter()
+        .find(|(frame, _)| *frame == playback.frame)
+        .map(|(_, command)| *command);
+    intent.purchase = playback
+        .replay
+        .purchases
+        .iter()
+        .find(|(frame, _)| *frame == playback.frame)
+        .map(|(_, purchase)| *purchase);
+    playback.frame += 1;
+}
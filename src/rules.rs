@@ -0,0 +1,258 @@
+use bevy::{
+    ecs::schedule::ShouldRun,
+    utils::{Duration, HashMap},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{events::MatchEnded, prelude::*};
+
+/// One way a match can end, a map can have several and the first one met ends the match
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum WinCondition {
+    /// A team owns every spawner
+    Domination,
+    /// When time runs out the team owning the most spawners wins, a tie is a draw
+    TimeLimit { seconds: f32 },
+    /// Each owned spawner scores a point a second for its owner, the first team to `target` wins
+    ScoreRace { target: f32 },
+    /// The first team to kill this many heroes wins
+    KillLimit { kills: u32 },
+    /// Heroes don't respawn, the last team with a hero standing wins
+    SuddenDeath,
+}
+
+/// How a map's matches are won
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MatchRules {
+    pub conditions: Vec<WinCondition>,
+}
+
+impl Default for MatchRules {
+    fn default() -> Self {
+        Self {
+            conditions: vec![WinCondition::Domination],
+        }
+    }
+}
+
+impl MatchRules {
+    /// Whether dead heroes come back
+    pub fn respawns(&self) -> bool {
+        !self.conditions.contains(&WinCondition::SuddenDeath)
+    }
+
+    /// The shortest time limit, if there is one
+    pub fn time_limit(&self) -> Option<Duration> {
+        self.conditions
+            .iter()
+            .filter_map(|condition| match condition {
+                WinCondition::TimeLimit { seconds } => Some(Duration::from_secs_f32(*seconds)),
+                _ => None,
+            })
+            .min()
+    }
+}
+
+/// Which win condition ended the match
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum WinReason {
+    Domination,
+    TimeLimit,
+    ScoreRace,
+    KillLimit,
+    SuddenDeath,
+}
+
+/// How a match ended, carried by `GameState::GameOver`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct MatchResult {
+    /// `None` for a draw
+    pub winner: Option<Team>,
+    pub reason: WinReason,
+    /// Game time from the start of the match to the end
+    pub duration: Duration,
+}
+
+impl MatchResult {
+    /// Whether the human player's team won
+    pub fn won(&self) -> bool {
+        self.winner == Some(Team::PLAYER)
+    }
+}
+
+/// Game time since the match started, for UI to show
+#[derive(Default, Debug)]
+pub struct MatchClock {
+    pub elapsed: Duration,
+    pub limit: Option<Duration>,
+}
+
+impl MatchClock {
+    /// Time left before the time limit, `None` without one
+    pub fn remaining(&self) -> Option<Duration> {
+        self.limit.map(|limit| limit.saturating_sub(self.elapsed))
+    }
+}
+
+/// Points and hero kills of each team this match
+#[derive(Default, Debug)]
+pub struct MatchScore {
+    pub points: HashMap<Team, f32>,
+    pub kills: HashMap<Team, u32>,
+}
+
+pub struct RulesPlugin;
+
+impl Plugin for RulesPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MatchRules>()
+            .init_resource::<MatchClock>()
+            .init_resource::<MatchScore>()
+            .add_system_set(SystemSet::on_enter(GameState::GamePlay).with_system(start_match))
+            .add_system_set(
+                SystemSet::on_update(GameState::GamePlay)
+                    .with_system(tick_match.label(TickMatchLabel))
+                    .with_system(check_win_conditions.after(TickMatchLabel)),
+            );
+    }
+}
+
+#[derive(SystemLabel, Clone, Hash, Debug, PartialEq, Eq)]
+struct TickMatchLabel;
+
+/// Run criteria for systems that react to the match ending, whatever its result
+pub fn entered_game_over(state: Res<State<GameState>>, mut was_over: Local<bool>) -> ShouldRun {
+    let over = matches!(state.current(), GameState::GameOver(_));
+    let entered = over && !*was_over;
+    *was_over = over;
+    if entered {
+        ShouldRun::Yes
+    } else {
+        ShouldRun::No
+    }
+}
+
+fn start_match(mut commands: Commands, map: Res<Assets<Map>>, current_map: Res<CurrentMap>) {
+    //create_map reports a missing map
+    let rules = map
+        .get(current_map.0.clone())
+        .map(|map| map.rules.clone())
+        .unwrap_or_default();
+    commands.insert_resource(MatchClock {
+        elapsed: Duration::ZERO,
+        limit: rules.time_limit(),
+    });
+    commands.insert_resource(MatchScore::default());
+    commands.insert_resource(rules);
+}
+
+fn tick_match(
+    spawners: Query<&Team, With<Spawner>>,
+    mut clock: ResMut<MatchClock>,
+    mut score: ResMut<MatchScore>,
+    time: Res<GameTime>,
+) {
+    clock.elapsed += time.delta();
+    for team in spawners.iter() {
+        *score.points.entry(*team).or_default() += time.delta_seconds();
+    }
+}
+
+/// The single team ahead by `count`, `None` if no team is or teams are tied for the lead
+fn sole_leader<T: PartialOrd + Copy>(counts: impl Iterator<Item = (Team, T)>) -> Option<Team> {
+    let mut counts: Vec<(Team, T)> = counts.collect();
+    counts.sort_by(|(team_a, a), (team_b, b)| {
+        b.partial_cmp(a)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then(team_a.cmp(team_b))
+    });
+    match counts.as_slice() {
+        [(leader, _)] => Some(*leader),
+        [(leader, lead), (_, runner_up), ..] if lead > runner_up => Some(*leader),
+        _ => None,
+    }
+}
+
+/// Ends the match with the first of the map's win conditions that is met
+fn check_win_conditions(
+    spawners: Query<Option<&Team>, With<Spawner>>,
+    heroes: Query<(&Team, &RespawnTimer), Or<(With<Player>, With<Enemy>)>>,
+    rules: Res<MatchRules>,
+    clock: Res<MatchClock>,
+    score: Res<MatchScore>,
+    mut state: ResMut<State<GameState>>,
+    mut ended: EventWriter<MatchEnded>,
+) {
+    let mut spawner_counts: HashMap<Team, u32> = HashMap::default();
+    for team in spawners.iter().flatten() {
+        *spawner_counts.entry(*team).or_default() += 1;
+    }
+
+    let result = rules
+        .conditions
+        .iter()
+        .find_map(|condition| match *condition {
+            WinCondition::Domination => {
+                let mut owners = spawners.iter();
+                match owners.next() {
+                    Some(Some(team)) if owners.all(|owner| owner == Some(team)) => {
+                        Some((Some(*team), WinReason::Domination))
+                    }
+                    _ => None,
+                }
+            }
+            WinCondition::TimeLimit { seconds } => {
+                if clock.elapsed < Duration::from_secs_f32(seconds) {
+                    return None;
+                }
+                let leader =
+                    sole_leader(spawner_counts.iter().map(|(team, count)| (*team, *count)));
+                Some((leader, WinReason::TimeLimit))
+            }
+            WinCondition::ScoreRace { target } => {
+                let finished = score
+                    .points
+                    .iter()
+                    .filter(|(_, points)| **points >= target)
+                    .map(|(team, points)| (*team, *points));
+                sole_leader(finished).map(|leader| (Some(leader), WinReason::ScoreRace))
+            }
+            WinCondition::KillLimit { kills } => {
+                let finished = score
+                    .kills
+                    .iter()
+                    .filter(|(_, count)| **count >= kills)
+                    .map(|(team, count)| (*team, *count));
+                sole_leader(finished).map(|leader| (Some(leader), WinReason::KillLimit))
+            }
+            WinCondition::SuddenDeath => {
+                let mut standing: Vec<Team> = heroes
+                    .iter()
+                    .filter(|(_, respawn)| !respawn.is_dead)
+                    .map(|(team, _)| *team)
+                    .collect();
+                standing.sort();
+                standing.dedup();
+                match standing.as_slice() {
+                    // Heroes are only spawned at the end of the first frame
+                    [] if heroes.is_empty() => None,
+                    [] => Some((None, WinReason::SuddenDeath)),
+                    [last] => Some((Some(*last), WinReason::SuddenDeath)),
+                    _ => None,
+                }
+            }
+        });
+
+    if let Some((winner, reason)) = result {
+        let result = MatchResult {
+            winner,
+            reason,
+            duration: clock.elapsed,
+        };
+        // Pausing on the same frame wins, the match ends once it's resumed
+        match state.set(GameState::GameOver(result)) {
+            Ok(()) => ended.send(MatchEnded(result)),
+            Err(e) => error!("{:?}", e),
+        }
+    }
+}
@@ -1,7 +1,15 @@
+use bevy::utils::{Duration, HashMap};
+
 use crate::{
-    assets::{BuildingFrames, ChickWalkFrames, PuppyWalkFrames},
-    minion::MinionBundle,
+    assets::{BuildingFrames, UnitSprites},
+    events::SpawnerOwnerChanged,
+    faction::Faction,
+    navigation::NavPath,
     prelude::*,
+    squad::Squad,
+    targeting::MinionTarget,
+    unit::{spawn_unit, UnitDef},
+    upgrade::{set_level, UpgradeBar},
     world_ui::{spawn_quad, BarMaterial, Percentage},
 };
 
@@ -17,8 +25,7 @@ impl Plugin for SpawnerPlugin {
         .add_system_set(
             SystemSet::on_update(GameState::GamePlay)
                 .with_system(minions_spawner_ai)
-                .with_system(spawner_capture_ai)
-                .with_system(spawner_win_con),
+                .with_system(spawner_capture_ai),
         )
         .register_type::<Spawner>();
     }
@@ -26,46 +33,33 @@ impl Plugin for SpawnerPlugin {
 
 pub fn spawn_initial_spawners(
     mut commands: Commands,
-    our_assets: Res<OurAssets>,
+    current_map: Res<CurrentMap>,
 
-    mut mesh_assets: ResMut<Assets<Mesh>>,
-    mut my_material_assets: ResMut<Assets<BarMaterial>>,
+    mut mesh_assets: Option<ResMut<Assets<Mesh>>>,
+    mut my_material_assets: Option<ResMut<Assets<BarMaterial>>>,
 
     map: Res<Assets<Map>>,
-    buildings: Res<BuildingFrames>,
+    buildings: Option<Res<BuildingFrames>>,
 ) {
-    let map = map.get(our_assets.map.clone()).unwrap();
+    //create_map reports a missing map
+    let map = match map.get(current_map.0.clone()) {
+        Some(map) => map,
+        None => return,
+    };
 
-    let (chicken_spawner_locations, dog_spawner_locations) = map
+    // Every spawner starts neutral, whichever team the map places it near
+    let spawner_locations = map
         .spawn_locations
         .iter()
-        .partition::<Vec<_>, _>(|(_, minion_type)| *minion_type == ChickenOrDog::Chicken);
-    let chicken_spawner_locations = chicken_spawner_locations
-        .into_iter()
-        .map(|(location, _)| location)
-        .collect();
-    let dog_spawner_locations = dog_spawner_locations
-        .into_iter()
-        .map(|(location, _)| location)
+        .map(|(location, _)| *location)
         .collect();
 
-    let mut spawners = Vec::new();
-
-    spawners.extend(spawn_minion_spawners(
+    let spawners = spawn_minion_spawners(
         &mut commands,
-        chicken_spawner_locations,
-        buildings.clone(),
-        &mut mesh_assets,
-        &mut my_material_assets,
-    ));
-
-    spawners.extend(spawn_minion_spawners(
-        &mut commands,
-        dog_spawner_locations,
-        buildings.clone(),
-        &mut mesh_assets,
-        &mut my_material_assets,
-    ));
+        spawner_locations,
+        buildings.as_deref(),
+        mesh_assets.as_mut().zip(my_material_assets.as_mut()),
+    );
 
     commands
         .spawn_bundle(TransformBundle::default())
@@ -81,226 +75,361 @@ pub fn spawn_initial_spawners(
 fn spawn_minion_spawners(
     commands: &mut Commands,
     spawn_locations: Vec<Vec2>,
-    buildings: BuildingFrames,
-    mesh_assets: &mut ResMut<Assets<Mesh>>,
-    my_material_assets: &mut ResMut<Assets<BarMaterial>>,
+    buildings: Option<&BuildingFrames>,
+    // Capture bars are only drawn when rendering
+    mut bar_assets: Option<(&mut ResMut<Assets<Mesh>>, &mut ResMut<Assets<BarMaterial>>)>,
 ) -> Vec<Entity> {
     let mut spawned = Vec::new();
 
     for spawn_location in spawn_locations {
-        let ui = spawn_quad(commands, mesh_assets, my_material_assets);
-        let sprite = buildings.frames[1].clone();
-        spawned.push(
+        let spawner = commands
+            .spawn_bundle(TransformBundle::from_transform(
+                Transform::from_translation(Vec3::new(spawn_location.x, spawn_location.y, 200.0)),
+            ))
+            .insert(Spawner::default())
+            .insert(Squad::default())
+            .insert(RigidBody::Sensor)
+            .insert(CollisionShape::Sphere { radius: 0.2 })
+            .insert(
+                CollisionLayers::none()
+                    .with_group(Layer::CaptureArea)
+                    .with_masks(&[Layer::Player, Layer::Enemy]),
+            )
+            .insert(crate::external::collisions::Collisions::default())
+            .insert(Name::new("Spawner"))
+            .id();
+
+        if let Some(buildings) = buildings {
+            commands
+                .entity(spawner)
+                .insert(buildings.frames[1].clone())
+                .insert(buildings.texture.clone())
+                .insert(Visibility::default());
+        }
+        if let Some((mesh_assets, my_material_assets)) = bar_assets.as_mut() {
+            let ui = spawn_quad(commands, mesh_assets, my_material_assets);
+            let upgrade_bar = spawn_quad(commands, mesh_assets, my_material_assets);
             commands
-                .spawn_bundle(SpriteSheetBundle {
-                    texture_atlas: buildings.texture.clone(),
-                    sprite,
-                    transform: Transform::from_translation(Vec3::new(
-                        spawn_location.x,
-                        spawn_location.y,
-                        200.0,
-                    )),
+                .entity(upgrade_bar)
+                .insert(Transform {
+                    translation: Vec3::new(0.0, 0.12, 0.0),
+                    scale: Vec3::new(0.3, 0.02, 1.0),
                     ..default()
                 })
-                .insert(Spawner::default())
-                .insert(RigidBody::Sensor)
-                .insert(CollisionShape::Sphere { radius: 0.2 })
-                .insert(
-                    CollisionLayers::none()
-                        .with_group(Layer::CaptureArea)
-                        .with_masks(&[Layer::Player, Layer::Enemy]),
-                )
-                .insert(crate::external::collisions::Collisions::default())
-                .insert(Name::new("Spawner"))
-                .add_child(ui)
-                .id(),
-        );
+                .insert(UpgradeBar);
+            commands.entity(spawner).push_children(&[ui, upgrade_bar]);
+        }
+        spawned.push(spawner);
     }
     spawned
 }
 
 fn minions_spawner_ai(
     mut commands: Commands,
-    assets: Res<OurAssets>,
-    mut spawners_query: Query<(&mut Spawner, &GlobalTransform, &ChickenOrDog)>,
-    chick_walk: Res<ChickWalkFrames>,
-    puppy_walk: Res<PuppyWalkFrames>,
+    mut spawners_query: Query<(Entity, &mut Spawner, &mut Squad, &GlobalTransform, &Team)>,
+    units: Res<Assets<UnitDef>>,
+    factions: Res<Factions>,
+    sprites: Option<Res<UnitSprites>>,
     parent: Query<Entity, With<MinionParentTag>>,
-    time: Res<Time>,
+    time: Res<GameTime>,
+    tuning: Res<GameTuning>,
+    difficulty: Res<Difficulty>,
+    mut rng: ResMut<GameRng>,
 ) {
     let parent = parent.single();
 
     let mut spawned = Vec::new();
-    for (mut spawner, transform, chicken_or_dog) in spawners_query.iter_mut() {
+    for (spawner_ent, mut spawner, mut squad, transform, team) in spawners_query.iter_mut() {
+        let tier = tuning.spawner_tiers.get(spawner.level);
+        let spawn_time = Duration::from_secs_f32(
+            difficulty.spawn_time(*team, tuning.spawn_time) / tier.spawn_rate.max(f32::EPSILON),
+        );
+        if spawner.spawn_timer.duration() != spawn_time {
+            spawner.spawn_timer.set_duration(spawn_time);
+        }
         spawner.spawn_timer.tick(time.delta());
-        if spawner.spawn_timer.just_finished() {
-            match chicken_or_dog {
-                ChickenOrDog::Chicken => {
-                    let ent = MinionBundle::spawn_chicken_minion(
-                        &mut commands,
-                        &assets,
-                        transform.translation.truncate(),
-                    )
-                    .unwrap();
-                    commands
-                        .entity(ent)
-                        .insert(chick_walk.texture.clone())
-                        .insert(DamageFlash {
-                            timer: Timer::from_seconds(0.0, false),
-                        })
-                        .insert(chick_walk.frames[0].clone())
-                        .insert(Animation {
-                            current_frame: 0,
-                            frames: chick_walk.frames.iter().map(|f| f.index).collect(),
-                            alt_frames: Some(
-                                chick_walk.alt_frames.iter().map(|f| f.index).collect(),
-                            ),
-                            playing_alt: false,
-                            playing: true,
-                            flip_x: false,
-                            flip_y: false,
-                            timer: Timer::from_seconds(1.0 / 10.0, true),
-                        });
-                    spawned.push(ent);
-                }
-                ChickenOrDog::Dog => {
-                    let ent = MinionBundle::spawn_dog_minion(
-                        &mut commands,
-                        &assets,
-                        transform.translation.truncate(),
-                    )
-                    .unwrap();
-                    commands
-                        .entity(ent)
-                        .insert(puppy_walk.texture.clone())
-                        .insert(DamageFlash {
-                            timer: Timer::from_seconds(0.0, false),
-                        })
-                        .insert(puppy_walk.frames[0].clone())
-                        .insert(Animation {
-                            current_frame: 0,
-                            frames: puppy_walk.frames.iter().map(|f| f.index).collect(),
-                            alt_frames: Some(
-                                puppy_walk.alt_frames.iter().map(|f| f.index).collect(),
-                            ),
-                            playing_alt: false,
-                            playing: true,
-                            flip_x: false,
-                            flip_y: false,
-                            timer: Timer::from_seconds(1.0 / 10.0, true),
-                        });
-                    spawned.push(ent);
-                }
+        let from_wave = !spawner.spawn_timer.just_finished() && spawner.queued > 0;
+        if spawner.spawn_timer.just_finished() || from_wave {
+            let handle = match factions
+                .get(*team)
+                .and_then(|faction| faction.pick_minion(&mut rng))
+            {
+                Some(handle) => handle,
+                None => continue,
+            };
+            let def = match units.get(handle) {
+                Some(def) => def,
+                None => continue,
+            };
+            let frames = sprites.as_ref().and_then(|sprites| sprites.get(handle));
+
+            let minion = spawn_unit(
+                &mut commands,
+                def,
+                frames,
+                *team,
+                transform.translation.truncate().extend(100.0),
+            );
+            let hp = difficulty.health(*team, def.hp) * tier.minion_health;
+            commands
+                .entity(minion)
+                .insert(Health(hp))
+                .insert(MaxHealth(hp))
+                .insert(Minion)
+                .insert(CaptureCount(def.capture_count))
+                .insert(squad.join(spawner_ent))
+                .insert(MinionTarget::default())
+                .insert(NavPath::default());
+            spawned.push(minion);
+            // Only counted off once it's made, so a failed pick doesn't lose a bought minion
+            if from_wave {
+                spawner.queued -= 1;
             }
         }
     }
     commands.entity(parent).push_children(&spawned);
 }
 
-fn spawner_capture_ai(
+pub(crate) fn spawner_capture_ai(
     mut commands: Commands,
     mut spawners: Query<(
         &Collisions,
         &mut Spawner,
         Entity,
-        &Children,
-        &mut TextureAtlasSprite,
+        Option<&Team>,
+        Option<&Children>,
+        Option<&mut TextureAtlasSprite>,
     )>,
-    mut ui_query: Query<&mut Percentage>,
-    player: Query<(&Player, &RespawnTimer), Without<Minion>>,
-    enemy: Query<(&Enemy, &RespawnTimer), Without<Minion>>,
-    minions: Query<&ChickenOrDog, (With<Minion>, Without<Spawner>)>,
-    time: Res<Time>,
-    buildings: Res<BuildingFrames>,
+    mut ui_query: Query<&mut Percentage, Without<UpgradeBar>>,
+    heroes: Query<(&Team, &RespawnTimer), (Or<(With<Player>, With<Enemy>)>, Without<Minion>)>,
+    minions: Query<(&Team, Option<&CaptureCount>), (With<Minion>, Without<Spawner>)>,
+    factions: Res<Factions>,
+    time: Res<GameTime>,
+    tuning: Res<GameTuning>,
+    buildings: Option<Res<BuildingFrames>>,
+    mut owner_changed: EventWriter<SpawnerOwnerChanged>,
 ) {
-    for (collisions, mut spawner, spawner_ent, spawner_children, mut sprite) in spawners.iter_mut()
+    for (collisions, mut spawner, spawner_ent, owner, spawner_children, mut sprite) in
+        spawners.iter_mut()
     {
+        spawner.contested_by = None;
         if collisions.is_empty() {
             continue;
         }
 
-        let mut progress_multiplier = 0.0;
-
-        collisions.entities().for_each(|ent| {
-            if let Ok((_, respawn)) = player.get(ent) {
+        // (heroes, minions) of each team on the spawner.
+        // Counted rather than summed as floats so collision order can't change the result,
+        // which keeps replays exact
+        let mut presence: HashMap<Team, (u32, u32)> = HashMap::default();
+        for ent in collisions.entities() {
+            if let Ok((team, respawn)) = heroes.get(ent) {
                 if !respawn.is_dead {
-                    progress_multiplier += 1.0;
+                    presence.entry(*team).or_default().0 += 1;
                 }
+            } else if let Ok((team, count)) = minions.get(ent) {
+                presence.entry(*team).or_default().1 += count.map(|count| count.0).unwrap_or(1);
             }
-        });
+        }
 
-        progress_multiplier -= collisions
-            .entities()
-            .filter(|ent| {
-                if let Ok((_, respawn)) = enemy.get(*ent) {
-                    !respawn.is_dead
-                } else {
-                    false
-                }
-            })
-            .count() as f32;
-
-        let minion_advantage: f32 = collisions
-            .entities()
-            .filter_map(|ent| {
-                let minion_type = minions.get(ent).ok()?;
-                Some(match minion_type {
-                    ChickenOrDog::Chicken => 0.2,
-                    ChickenOrDog::Dog => -0.2,
-                })
+        // The strongest team captures at the rate it outweighs the runner up by
+        let mut ranked: Vec<(Team, f32)> = presence
+            .into_iter()
+            .map(|(team, (heroes, minions))| {
+                let weight = heroes as f32 * tuning.hero_capture_weight
+                    + minions as f32 * tuning.minion_capture_weight;
+                (team, weight)
             })
-            .sum();
-        progress_multiplier += minion_advantage;
+            .collect();
+        ranked.sort_by(|(team_a, a), (team_b, b)| {
+            b.partial_cmp(a)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then(team_a.cmp(team_b))
+        });
+        let (leader, lead) = match ranked.first() {
+            Some(leader) => *leader,
+            None => continue,
+        };
+        let runner_up = ranked.get(1).map(|(_, weight)| *weight).unwrap_or(0.0);
+        let advantage = lead - runner_up;
+        if advantage > 0.0 && owner.map(|owner| *owner != leader) == Some(true) {
+            spawner.contested_by = Some(leader);
+        }
+        if advantage <= 0.0 {
+            continue;
+        }
 
-        let delta_progress = progress_multiplier * (time.delta_seconds() / spawner.capture_time);
+        let delta_progress = advantage * (time.delta_seconds() / tuning.capture_time);
 
-        if (spawner.capture_progress <= -1.0 && delta_progress < 0.0)
-            || (spawner.capture_progress >= 1.0 && delta_progress > 0.0)
-        {
-            continue;
+        if spawner.capturing.is_none() || spawner.capturing == Some(leader) {
+            if spawner.capture_progress >= 1.0 {
+                continue;
+            }
+            spawner.capturing = Some(leader);
+            spawner.capture_progress = (spawner.capture_progress + delta_progress).min(1.0);
+        } else {
+            // Another team's progress has to be undone before the leader can make its own
+            spawner.capture_progress -= delta_progress;
+            if spawner.capture_progress <= 0.0 {
+                spawner.capturing = Some(leader);
+                spawner.capture_progress = -spawner.capture_progress;
+            }
         }
 
-        spawner.capture_progress += delta_progress;
+        let faction = spawner.capturing.and_then(|team| factions.get(team));
 
-        for child in spawner_children.iter() {
+        for child in spawner_children
+            .into_iter()
+            .flat_map(|children| children.iter())
+        {
             if let Ok(mut percentage) = ui_query.get_mut(*child) {
                 percentage.value = spawner.capture_progress;
+                percentage.color = faction.map(|faction| faction.color).unwrap_or(Color::GRAY);
             }
         }
 
-        if spawner.capture_progress >= -0.5 && spawner.capture_progress <= -0.1 {
-            *sprite = buildings.frames[3].clone();
-        }
+        let mut set_sprite = |state: BuildingState| {
+            if let (Some(sprite), Some(buildings)) = (sprite.as_mut(), buildings.as_ref()) {
+                **sprite = building_sprite(buildings, faction, state);
+            }
+        };
+
         if spawner.capture_progress <= 0.5 && spawner.capture_progress >= 0.1 {
-            *sprite = buildings.frames[4].clone();
+            set_sprite(BuildingState::Capturing);
         }
 
-        if spawner.capture_progress <= -0.9 {
-            *sprite = buildings.frames[0].clone();
-            commands.entity(spawner_ent).insert(ChickenOrDog::Dog);
-        } else if spawner.capture_progress >= 0.9 {
-            *sprite = buildings.frames[2].clone();
-            commands.entity(spawner_ent).insert(ChickenOrDog::Chicken);
-        } else if spawner.capture_progress.abs() <= 0.15 {
-            *sprite = buildings.frames[1].clone();
-            commands.entity(spawner_ent).remove::<ChickenOrDog>();
+        let owner = owner.copied();
+        if spawner.capture_progress >= 0.9 {
+            set_sprite(BuildingState::Owned);
+            if let Some(team) = spawner.capturing {
+                commands.entity(spawner_ent).insert(team);
+                if owner != Some(team) {
+                    owner_changed.send(SpawnerOwnerChanged {
+                        spawner: spawner_ent,
+                        previous: owner,
+                        owner: Some(team),
+                    });
+                }
+            }
+        } else if spawner.capture_progress <= 0.15 {
+            set_sprite(BuildingState::Neutral);
+            commands.entity(spawner_ent).remove::<Team>();
+            if owner.is_some() {
+                owner_changed.send(SpawnerOwnerChanged {
+                    spawner: spawner_ent,
+                    previous: owner,
+                    owner: None,
+                });
+            }
+            spawner.queued = 0;
+            if spawner.level != 0 {
+                set_level(
+                    &mut commands,
+                    spawner_ent,
+                    &mut spawner,
+                    0,
+                    &tuning.spawner_tiers,
+                );
+            }
         }
     }
 }
 
-fn spawner_win_con(
-    spawners: Query<Option<&ChickenOrDog>, With<Spawner>>,
-    mut state: ResMut<State<GameState>>,
-) {
-    let total_spawners = spawners.iter().count();
-    let captured_spawners = spawners.iter().flatten().cloned();
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum BuildingState {
+    Neutral,
+    Capturing,
+    Owned,
+}
+
+const NEUTRAL_BUILDING_FRAME: usize = 1;
+
+/// The building in the faction's art, or the neutral building tinted with its color
+fn building_sprite(
+    buildings: &BuildingFrames,
+    faction: Option<&Faction>,
+    state: BuildingState,
+) -> TextureAtlasSprite {
+    let faction = match faction {
+        Some(faction) if state != BuildingState::Neutral => faction,
+        _ => return buildings.frames[NEUTRAL_BUILDING_FRAME].clone(),
+    };
+
+    match faction.building_frames {
+        Some((owned, capturing)) => {
+            let index = if state == BuildingState::Owned {
+                owned
+            } else {
+                capturing
+            };
+            buildings.frames[index].clone()
+        }
+        None => {
+            let mut sprite = buildings.frames[NEUTRAL_BUILDING_FRAME].clone();
+            sprite.color = faction.color;
+            sprite
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::{events::GameEventsPlugin, game_time::GameTimePlugin};
+
+    /// An app that runs only spawner capturing, ten frames to a second
+    pub(crate) fn capture_app() -> App {
+        let mut app = App::new();
+        app.init_resource::<Time>()
+            .insert_resource(GameTime::fixed(Duration::from_millis(100)))
+            .add_plugin(GameTimePlugin)
+            .init_resource::<GameTuning>()
+            .init_resource::<Factions>()
+            .add_plugin(GameEventsPlugin)
+            .add_system(spawner_capture_ai);
+        app
+    }
+
+    /// A spawner fully captured by `owner`, with a hero of `attacker` standing on it
+    pub(crate) fn spawner_under_attack(app: &mut App, owner: Team, attacker: Team) -> Entity {
+        let hero = app
+            .world
+            .spawn()
+            .insert(Player)
+            .insert(attacker)
+            .insert(RespawnTimer::default())
+            .id();
+        app.world
+            .spawn()
+            .insert(Spawner {
+                capture_progress: 1.0,
+                capturing: Some(owner),
+                ..default()
+            })
+            .insert(owner)
+            .insert(Collisions::with_entities([hero]))
+            .id()
+    }
+
+    #[test]
+    fn enemy_hero_contests_an_owned_spawner() {
+        let mut app = capture_app();
+        let spawner = spawner_under_attack(&mut app, Team(1), Team(0));
+        app.update();
+
+        let state = app.world.get::<Spawner>(spawner).unwrap();
+        assert_eq!(state.contested_by, Some(Team(0)));
+        assert!(state.capture_progress < 1.0);
+        assert_eq!(app.world.get::<Team>(spawner), Some(&Team(1)));
+    }
 
-    let (chicken_captured, dog_captured) =
-        captured_spawners.partition::<Vec<_>, _>(|ty| *ty == ChickenOrDog::Chicken);
+    #[test]
+    fn own_hero_leaves_a_spawner_uncontested() {
+        let mut app = capture_app();
+        let spawner = spawner_under_attack(&mut app, Team(1), Team(1));
+        app.update();
 
-    if chicken_captured.len() == total_spawners {
-        state.push(GameState::GameOver { won: true }).unwrap();
-    } else if dog_captured.len() == total_spawners {
-        state.push(GameState::GameOver { won: false }).unwrap();
+        let state = app.world.get::<Spawner>(spawner).unwrap();
+        assert_eq!(state.contested_by, None);
+        assert_eq!(state.capture_progress, 1.0);
     }
 }
@@ -0,0 +1,134 @@
+use bevy::utils::HashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::{player::PlayerInputLabel, prelude::*};
+
+/// Spacing between the rings of a squad's formation
+const FORMATION_SPACING: f32 = 0.12;
+/// Minions in the first ring, each ring out holds this many more
+const FORMATION_RING: usize = 6;
+/// Minions closer together than this push each other apart
+const SEPARATION_RADIUS: f32 = 0.12;
+
+/// An order the player gives to every squad on their team
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SquadCommand {
+    /// Gather around where the player is standing
+    Rally,
+    /// Each squad marches on the closest spawner its team doesn't own
+    AttackSpawner,
+    /// Stay put and only fight what comes into reach
+    Hold,
+    /// Go back to picking targets on their own
+    Release,
+}
+
+/// What a team's squads are currently doing
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SquadOrder {
+    Free,
+    Rally(Vec2),
+    AttackSpawner,
+    Hold,
+}
+
+impl Default for SquadOrder {
+    fn default() -> Self {
+        SquadOrder::Free
+    }
+}
+
+/// The standing order of each team, teams without one act freely
+#[derive(Default)]
+pub struct SquadOrders(pub HashMap<Team, SquadOrder>);
+
+impl SquadOrders {
+    pub fn get(&self, team: Team) -> SquadOrder {
+        self.0.get(&team).copied().unwrap_or_default()
+    }
+}
+
+/// Put on spawners, every minion a spawner makes joins its squad
+#[derive(Component, Default)]
+pub struct Squad {
+    next_slot: usize,
+}
+
+impl Squad {
+    /// Hands out formation slots in the order minions join
+    pub fn join(&mut self, squad: Entity) -> SquadMember {
+        let slot = self.next_slot;
+        self.next_slot += 1;
+        SquadMember { squad, slot }
+    }
+}
+
+#[derive(Component, Clone, Copy, Debug)]
+pub struct SquadMember {
+    /// The spawner the minion came from
+    pub squad: Entity,
+    pub slot: usize,
+}
+
+/// Where a member stands relative to the point its squad is heading for.
+/// The first slot is the center, the rest fill rings around it
+pub fn formation_offset(slot: usize) -> Vec2 {
+    if slot == 0 {
+        return Vec2::ZERO;
+    }
+    let mut ring = 1;
+    let mut first = 1;
+    while slot >= first + FORMATION_RING * ring {
+        first += FORMATION_RING * ring;
+        ring += 1;
+    }
+    let angle = (slot - first) as f32 / (FORMATION_RING * ring) as f32 * std::f32::consts::TAU;
+    Vec2::new(angle.cos(), angle.sin()) * FORMATION_SPACING * ring as f32
+}
+
+/// Steering away from `neighbours` that are too close, stronger the closer they are
+pub fn separation(position: Vec2, neighbours: impl Iterator<Item = Vec2>) -> Vec2 {
+    neighbours
+        .map(|neighbour| position - neighbour)
+        .filter(|away| away.length() < SEPARATION_RADIUS)
+        .map(|away| match away.try_normalize() {
+            Some(direction) => direction * (1.0 - away.length() / SEPARATION_RADIUS),
+            // Exactly on top of each other, both fall back to the same side until one moves
+            None => Vec2::X,
+        })
+        .fold(Vec2::ZERO, |sum, push| sum + push)
+}
+
+pub struct SquadPlugin;
+
+impl Plugin for SquadPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SquadOrders>()
+            .add_system_set(SystemSet::on_enter(GameState::GamePlay).with_system(reset_orders))
+            .add_system_set(
+                SystemSet::on_update(GameState::GamePlay)
+                    .with_system(apply_squad_commands.after(PlayerInputLabel)),
+            );
+    }
+}
+
+fn reset_orders(mut orders: ResMut<SquadOrders>) {
+    orders.0.clear();
+}
+
+/// Turns the command in each player's intent into their team's standing order
+fn apply_squad_commands(
+    players: Query<(&PlayerIntent, &GlobalTransform, &Team), With<Player>>,
+    mut orders: ResMut<SquadOrders>,
+) {
+    for (intent, transform, team) in players.iter() {
+        let order = match intent.command {
+            Some(SquadCommand::Rally) => SquadOrder::Rally(transform.translation.truncate()),
+            Some(SquadCommand::AttackSpawner) => SquadOrder::AttackSpawner,
+            Some(SquadCommand::Hold) => SquadOrder::Hold,
+            Some(SquadCommand::Release) => SquadOrder::Free,
+            None => continue,
+        };
+        orders.0.insert(*team, order);
+    }
+}
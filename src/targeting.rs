@@ -0,0 +1,254 @@
+use serde::Deserialize;
+
+use crate::prelude::*;
+
+/// The kinds of thing a minion can go after
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub enum TargetKind {
+    /// A spawner no team owns
+    NeutralSpawner,
+    /// A spawner the minion's team owns that another team is taking
+    DefendSpawner,
+    /// A spawner another team owns
+    EnemySpawner,
+    /// Another team's player or enemy hero
+    Hero,
+    /// Another team's minion
+    Minion,
+}
+
+impl TargetKind {
+    /// What a thing is to a minion of `team`, `None` if the minion has no reason to go to it
+    pub fn classify(
+        team: Team,
+        target_team: Option<Team>,
+        spawner: Option<&Spawner>,
+        minion: bool,
+    ) -> Option<Self> {
+        if let Some(spawner) = spawner {
+            return match target_team {
+                None => Some(TargetKind::NeutralSpawner),
+                Some(owner) if owner != team => Some(TargetKind::EnemySpawner),
+                Some(_) if spawner.contested_by.is_some() => Some(TargetKind::DefendSpawner),
+                Some(_) => None,
+            };
+        }
+        match target_team {
+            Some(target_team) if target_team == team => None,
+            _ if minion => Some(TargetKind::Minion),
+            _ => Some(TargetKind::Hero),
+        }
+    }
+
+    /// Heroes and minions, which minions fight rather than stand on
+    pub fn is_unit(self) -> bool {
+        matches!(self, TargetKind::Hero | TargetKind::Minion)
+    }
+}
+
+/// How minions choose between targets, part of `GameTuning`
+#[derive(Debug, Clone, Deserialize)]
+pub struct MinionTargeting {
+    /// Most wanted first, kinds left out come after all the listed ones
+    pub priorities: Vec<TargetKind>,
+    /// How far from their home spawner minions will chase heroes and minions
+    pub leash: f32,
+    /// How much closer another target of the same priority has to be
+    /// to draw a minion away from the one it's already after
+    pub stickiness: f32,
+}
+
+impl Default for MinionTargeting {
+    fn default() -> Self {
+        Self {
+            priorities: vec![
+                TargetKind::NeutralSpawner,
+                TargetKind::DefendSpawner,
+                TargetKind::Hero,
+                TargetKind::Minion,
+                TargetKind::EnemySpawner,
+            ],
+            leash: 2.5,
+            stickiness: 0.3,
+        }
+    }
+}
+
+impl MinionTargeting {
+    /// Lower is wanted more
+    pub fn rank(&self, kind: TargetKind) -> usize {
+        self.priorities
+            .iter()
+            .position(|priority| *priority == kind)
+            .unwrap_or(self.priorities.len())
+    }
+
+    /// Whether a minion from the spawner at `home` will go after something of `kind` at `position`.
+    /// Minions without a home chase anything
+    pub fn within_leash(&self, kind: TargetKind, position: Vec2, home: Option<Vec2>) -> bool {
+        match home {
+            Some(home) if kind.is_unit() => home.distance(position) <= self.leash,
+            _ => true,
+        }
+    }
+}
+
+/// A target a minion could pick this frame
+#[derive(Clone, Copy, Debug)]
+pub struct TargetCandidate {
+    pub entity: Entity,
+    pub kind: TargetKind,
+    pub position: Vec2,
+}
+
+/// The target a minion is currently after, kept between frames so it sticks with it
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub struct MinionTarget(pub Option<Entity>);
+
+/// Picks the highest priority candidate, the closest to `from` among equals.
+/// `current` counts as `stickiness` closer than it is, and remaining ties go to the lowest entity
+/// so the same world always gives the same pick
+pub fn pick_target(
+    candidates: &[TargetCandidate],
+    from: Vec2,
+    current: Option<Entity>,
+    targeting: &MinionTargeting,
+) -> Option<TargetCandidate> {
+    candidates
+        .iter()
+        .map(|candidate| {
+            let mut distance = candidate.position.distance(from);
+            if Some(candidate.entity) == current {
+                distance -= targeting.stickiness;
+            }
+            (targeting.rank(candidate.kind), distance, candidate)
+        })
+        .min_by(|(rank_a, distance_a, a), (rank_b, distance_b, b)| {
+            rank_a
+                .cmp(rank_b)
+                .then(
+                    distance_a
+                        .partial_cmp(distance_b)
+                        .unwrap_or(std::cmp::Ordering::Equal),
+                )
+                .then(a.entity.cmp(&b.entity))
+        })
+        .map(|(_, _, candidate)| *candidate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spawner::tests::{capture_app, spawner_under_attack};
+
+    fn candidate(id: u32, kind: TargetKind, x: f32) -> TargetCandidate {
+        TargetCandidate {
+            entity: Entity::from_raw(id),
+            kind,
+            position: Vec2::new(x, 0.0),
+        }
+    }
+
+    #[test]
+    fn classifies_spawners_and_units() {
+        let team = Team(1);
+        let calm = Spawner::default();
+
+        let mut app = capture_app();
+        let attacked = spawner_under_attack(&mut app, team, Team(0));
+        app.update();
+        let contested = app.world.get::<Spawner>(attacked).unwrap();
+
+        let classify = TargetKind::classify;
+        assert_eq!(
+            classify(team, None, Some(&calm), false),
+            Some(TargetKind::NeutralSpawner)
+        );
+        assert_eq!(
+            classify(team, Some(Team(0)), Some(&calm), false),
+            Some(TargetKind::EnemySpawner)
+        );
+        assert_eq!(
+            classify(team, Some(team), Some(contested), false),
+            Some(TargetKind::DefendSpawner)
+        );
+        assert_eq!(classify(team, Some(team), Some(&calm), false), None);
+        assert_eq!(classify(team, Some(team), None, true), None);
+        assert_eq!(
+            classify(team, Some(Team(2)), None, true),
+            Some(TargetKind::Minion)
+        );
+        assert_eq!(
+            classify(team, Some(Team(0)), None, false),
+            Some(TargetKind::Hero)
+        );
+    }
+
+    #[test]
+    fn higher_priority_wins_over_distance() {
+        let candidates = [
+            candidate(1, TargetKind::Minion, 0.5),
+            candidate(2, TargetKind::Hero, 2.0),
+        ];
+
+        let targeting = MinionTargeting::default();
+        let picked = pick_target(&candidates, Vec2::ZERO, None, &targeting).unwrap();
+        assert_eq!(picked.entity, Entity::from_raw(2));
+
+        let minions_first = MinionTargeting {
+            priorities: vec![TargetKind::Minion],
+            ..default()
+        };
+        let picked = pick_target(&candidates, Vec2::ZERO, None, &minions_first).unwrap();
+        assert_eq!(picked.entity, Entity::from_raw(1));
+    }
+
+    #[test]
+    fn leash_cuts_off_units_but_not_spawners() {
+        let targeting = MinionTargeting::default();
+        let home = Some(Vec2::ZERO);
+        let near = Vec2::new(targeting.leash - 0.1, 0.0);
+        let far = Vec2::new(targeting.leash + 0.1, 0.0);
+
+        assert!(targeting.within_leash(TargetKind::Hero, near, home));
+        assert!(!targeting.within_leash(TargetKind::Hero, far, home));
+        assert!(!targeting.within_leash(TargetKind::Minion, far, home));
+        assert!(targeting.within_leash(TargetKind::EnemySpawner, far, home));
+        assert!(targeting.within_leash(TargetKind::Hero, far, None));
+    }
+
+    #[test]
+    fn sticks_with_current_target_until_another_is_clearly_closer() {
+        let targeting = MinionTargeting::default();
+        let current = Some(Entity::from_raw(1));
+
+        let slightly_closer = [
+            candidate(1, TargetKind::Hero, 1.0),
+            candidate(2, TargetKind::Hero, 1.0 - targeting.stickiness / 2.0),
+        ];
+        let picked = pick_target(&slightly_closer, Vec2::ZERO, current, &targeting).unwrap();
+        assert_eq!(picked.entity, Entity::from_raw(1));
+
+        let much_closer = [
+            candidate(1, TargetKind::Hero, 1.0),
+            candidate(2, TargetKind::Hero, 1.0 - targeting.stickiness * 2.0),
+        ];
+        let picked = pick_target(&much_closer, Vec2::ZERO, current, &targeting).unwrap();
+        assert_eq!(picked.entity, Entity::from_raw(2));
+    }
+
+    #[test]
+    fn ties_go_to_the_lowest_entity() {
+        let targeting = MinionTargeting::default();
+        let forwards = [
+            candidate(3, TargetKind::Minion, 1.0),
+            candidate(5, TargetKind::Minion, 1.0),
+        ];
+        let backwards = [forwards[1], forwards[0]];
+
+        for candidates in [forwards, backwards] {
+            let picked = pick_target(&candidates, Vec2::ZERO, None, &targeting).unwrap();
+            assert_eq!(picked.entity, Entity::from_raw(3));
+        }
+    }
+}
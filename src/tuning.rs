@@ -0,0 +1,129 @@
+use std::{fs, path::PathBuf};
+
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    reflect::TypeUuid,
+    utils::BoxedFuture,
+};
+use serde::Deserialize;
+
+use crate::{
+    ability::Abilities, damage::DamageTuning, difficulty::DifficultyScaling,
+    economy::EconomyTuning, prelude::*, targeting::MinionTargeting, upgrade::SpawnerTiers,
+    weapon::Weapons,
+};
+
+/// Balance numbers that aren't a property of a single unit.
+/// Loaded from `main.tuning` and copied into a resource whenever the file changes,
+/// so systems read the live values every frame
+#[derive(Debug, Clone, Deserialize, TypeUuid)]
+#[uuid = "4f1e2a7c-9b3d-4c85-8e60-2d7a5b9c3f14"]
+pub struct GameTuning {
+    /// Seconds a dead hero waits before respawning
+    pub respawn_time: f32,
+    /// Seconds between minions from an owned spawner
+    pub spawn_time: f32,
+    /// Seconds a single hero needs to take a spawner from neutral to fully captured
+    pub capture_time: f32,
+    /// How much each hero and minion standing on a spawner pushes its capture
+    pub hero_capture_weight: f32,
+    pub minion_capture_weight: f32,
+    /// What the custom difficulty scales the AI teams by
+    #[serde(default)]
+    pub custom_difficulty: DifficultyScaling,
+    /// How minions pick what to go after
+    #[serde(default)]
+    pub minion_targeting: MinionTargeting,
+    /// Income, bounties and prices
+    #[serde(default)]
+    pub economy: EconomyTuning,
+    /// What each spawner upgrade costs and does
+    #[serde(default)]
+    pub spawner_tiers: SpawnerTiers,
+    /// Invulnerability, hit feedback and knockback
+    #[serde(default)]
+    pub damage: DamageTuning,
+    /// Every weapon a hero can carry
+    #[serde(default)]
+    pub weapons: Weapons,
+    /// Every ability a hero can have
+    #[serde(default)]
+    pub abilities: Abilities,
+}
+
+impl Default for GameTuning {
+    fn default() -> Self {
+        Self {
+            respawn_time: 2.0,
+            spawn_time: 5.0,
+            capture_time: 5.0,
+            hero_capture_weight: 1.0,
+            minion_capture_weight: 0.2,
+            custom_difficulty: DifficultyScaling::default(),
+            minion_targeting: MinionTargeting::default(),
+            economy: EconomyTuning::default(),
+            spawner_tiers: SpawnerTiers::default(),
+            damage: DamageTuning::default(),
+            weapons: Weapons::default(),
+            abilities: Abilities::default(),
+        }
+    }
+}
+
+impl GameTuning {
+    /// Reads the tuning file straight from `assets_dir`, for apps that don't use the asset server
+    pub fn load_from_dir(assets_dir: &str, path: &str) -> anyhow::Result<Self> {
+        let path = PathBuf::from(assets_dir).join(path);
+        let contents = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        ron::de::from_str(&contents).with_context(|| format!("failed to parse {}", path.display()))
+    }
+}
+
+pub struct TuningPlugin;
+
+impl Plugin for TuningPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<GameTuning>()
+            .init_asset_loader::<GameTuningLoader>()
+            .init_resource::<GameTuning>()
+            .add_system(apply_tuning);
+    }
+}
+
+/// Copies the tuning file into the `GameTuning` resource when it loads or is edited
+fn apply_tuning(
+    mut events: EventReader<AssetEvent<GameTuning>>,
+    tunings: Res<Assets<GameTuning>>,
+    mut tuning: ResMut<GameTuning>,
+) {
+    for event in events.iter() {
+        if let AssetEvent::Created { handle } | AssetEvent::Modified { handle } = event {
+            if let Some(loaded) = tunings.get(handle) {
+                *tuning = loaded.clone();
+                info!("Applied game tuning");
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct GameTuningLoader;
+
+impl AssetLoader for GameTuningLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let tuning = ron::de::from_bytes::<GameTuning>(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(tuning));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["tuning"]
+    }
+}
@@ -0,0 +1,259 @@
+use std::path::PathBuf;
+
+use bevy::{
+    asset::{AssetLoader, AssetPath, LoadContext, LoadedAsset},
+    reflect::TypeUuid,
+    utils::BoxedFuture,
+};
+use serde::Deserialize;
+
+use crate::{
+    assets::{GraphicsDesc, SpriteDesc, UnitFrames},
+    damage::{Armor, Invulnerable, Knockback},
+    economy::LastAttacker,
+    prelude::*,
+};
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct MeleeStats {
+    pub damage: f32,
+    pub range: f32,
+    pub cooldown: f32,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct RangedStats {
+    pub damage: f32,
+    pub range: f32,
+    pub cooldown: f32,
+    pub bullet_speed: f32,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct HealStats {
+    pub amount: f32,
+    pub range: f32,
+    pub cooldown: f32,
+}
+
+fn one() -> u32 {
+    1
+}
+
+/// Everything that makes up a unit, loaded from a `.unit` file
+#[derive(Debug, Clone, Deserialize, TypeUuid)]
+#[uuid = "2b6f8b4e-3c1d-4f7a-9a55-6d0e9c1b7f21"]
+pub struct UnitDef {
+    pub name: String,
+    pub sprite_sheet: String,
+    pub walk_animation: String,
+    pub attack_animation: String,
+    pub frame_time: f32,
+    pub collider_radius: f32,
+    pub hp: f32,
+    pub speed: f32,
+    #[serde(default)]
+    pub armor: Armor,
+    #[serde(default)]
+    pub melee: Option<MeleeStats>,
+    #[serde(default)]
+    pub ranged: Option<RangedStats>,
+    /// Name of the weapon in `GameTuning::weapons` a hero shoots with, in place of `ranged`
+    #[serde(default)]
+    pub weapon: Option<String>,
+    /// Names of the abilities in `GameTuning::abilities` a hero can use, in the order they're bound
+    #[serde(default)]
+    pub abilities: Vec<String>,
+    #[serde(default)]
+    pub heal: Option<HealStats>,
+    /// How many minions this unit counts as when capturing a spawner
+    #[serde(default = "one")]
+    pub capture_count: u32,
+    pub layer: Layer,
+    /// The sprite sheet is drawn facing right rather than left
+    #[serde(default)]
+    pub faces_right: bool,
+
+    // Filled in by the loader from the paths above
+    #[serde(skip)]
+    pub texture: Handle<Image>,
+    #[serde(skip)]
+    pub walk_frames: Vec<SpriteDesc>,
+    #[serde(skip)]
+    pub attack_frames: Vec<SpriteDesc>,
+}
+
+pub struct UnitPlugin;
+
+impl Plugin for UnitPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<UnitDef>()
+            .init_asset_loader::<UnitDefLoader>()
+            .add_system(track_velocity);
+    }
+}
+
+/// Spawns the parts every unit shares.
+/// Callers add whatever makes it a player, enemy or minion
+pub fn spawn_unit(
+    commands: &mut Commands,
+    def: &UnitDef,
+    frames: Option<&UnitFrames>,
+    team: Team,
+    translation: Vec3,
+) -> Entity {
+    let unit = commands
+        .spawn_bundle(TransformBundle::from_transform(
+            Transform::from_translation(translation),
+        ))
+        .insert(team)
+        .insert(MovementStats { speed: def.speed })
+        .insert(Health(def.hp))
+        .insert(MaxHealth(def.hp))
+        .insert(DamageFlash {
+            timer: Timer::from_seconds(0.0, false),
+        })
+        .insert(RigidBody::Dynamic)
+        .insert(CollisionShape::Sphere {
+            radius: def.collider_radius,
+        })
+        .insert(RotationConstraints::lock())
+        .insert(CollisionLayers::all_masks::<Layer>().with_group(def.layer))
+        .insert(Name::new(def.name.clone()))
+        .insert(ObservedVelocity::default())
+        .insert(LastAttacker::default())
+        .insert(def.armor)
+        .insert(Invulnerable::default())
+        .insert(Knockback::default())
+        .id();
+
+    if let Some(melee) = def.melee {
+        commands.entity(unit).insert(MeleeAttack {
+            damage: melee.damage,
+            range: melee.range,
+            cooldown: Timer::from_seconds(melee.cooldown, true),
+        });
+    }
+    if let Some(ranged) = def.ranged {
+        commands.entity(unit).insert(RangedAttack {
+            damage: ranged.damage,
+            range: ranged.range,
+            bullet_speed: ranged.bullet_speed,
+            cooldown: Timer::from_seconds(ranged.cooldown, true),
+        });
+    }
+    if let Some(heal) = def.heal {
+        commands.entity(unit).insert(Healer {
+            amount: heal.amount,
+            range: heal.range,
+            cooldown: Timer::from_seconds(heal.cooldown, true),
+        });
+    }
+
+    let mut animation = Animation {
+        playing: true,
+        faces_right: def.faces_right,
+        timer: Timer::from_seconds(def.frame_time, true),
+        ..default()
+    };
+    if let Some(frames) = frames.filter(|frames| !frames.frames.is_empty()) {
+        animation.frames = frames.frames.iter().map(|f| f.index).collect();
+        animation.alt_frames = Some(frames.alt_frames.iter().map(|f| f.index).collect());
+        commands
+            .entity(unit)
+            .insert(frames.frames[0].clone())
+            .insert(frames.texture.clone())
+            .insert(Visibility::default());
+    }
+    commands.entity(unit).insert(animation);
+
+    unit
+}
+
+/// Faster than any unit walks, dashes or is knocked back
+const MAX_TRACKED_SPEED: f32 = 10.0;
+
+fn track_velocity(
+    mut units: Query<(&GlobalTransform, &mut ObservedVelocity)>,
+    time: Res<GameTime>,
+) {
+    if time.delta_seconds() <= 0.0 {
+        return;
+    }
+    for (transform, mut observed) in units.iter_mut() {
+        let position = transform.translation.truncate();
+        if let Some(last_position) = observed.last_position {
+            let velocity = (position - last_position) / time.delta_seconds();
+            if velocity.length() > MAX_TRACKED_SPEED {
+                // Teleported, like a hero respawning, rather than moved
+                observed.velocity = Vec2::ZERO;
+            } else {
+                // Smoothed so a single jittery frame doesn't throw the aim off
+                observed.velocity = observed.velocity.lerp(velocity, 0.5);
+            }
+        }
+        observed.last_position = Some(position);
+    }
+}
+
+#[derive(Default)]
+pub struct UnitDefLoader;
+
+impl AssetLoader for UnitDefLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let def = ron::de::from_bytes::<UnitDef>(bytes)?;
+            let (def, sprite_sheet) = resolve_unit(load_context, def).await?;
+            load_context.set_default_asset(LoadedAsset::new(def).with_dependency(sprite_sheet));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["unit"]
+    }
+}
+
+/// Reads the unit file at `path` for loaders that bundle units into their own asset
+pub async fn read_unit(
+    load_context: &mut LoadContext<'_>,
+    path: &str,
+) -> anyhow::Result<(UnitDef, AssetPath<'static>)> {
+    let bytes = load_context
+        .read_asset_bytes(path)
+        .await
+        .with_context(|| format!("failed to read unit {}", path))?;
+    let def = ron::de::from_bytes::<UnitDef>(&bytes)
+        .with_context(|| format!("failed to parse unit {}", path))?;
+    resolve_unit(load_context, def).await
+}
+
+/// Fills in the animation frames and returns the sprite sheet the unit depends on
+async fn resolve_unit(
+    load_context: &mut LoadContext<'_>,
+    mut def: UnitDef,
+) -> anyhow::Result<(UnitDef, AssetPath<'static>)> {
+    def.walk_frames = read_animation(load_context, &def.walk_animation).await?;
+    def.attack_frames = read_animation(load_context, &def.attack_animation).await?;
+
+    let sprite_sheet = AssetPath::new(PathBuf::from(&def.sprite_sheet), None);
+    def.texture = load_context.get_handle(sprite_sheet.clone());
+    Ok((def, sprite_sheet))
+}
+
+async fn read_animation(
+    load_context: &mut LoadContext<'_>,
+    path: &str,
+) -> anyhow::Result<Vec<SpriteDesc>> {
+    let bytes = load_context
+        .read_asset_bytes(path)
+        .await
+        .with_context(|| format!("failed to read animation {}", path))?;
+    let desc = ron::de::from_bytes::<GraphicsDesc>(&bytes)
+        .with_context(|| format!("failed to parse animation {}", path))?;
+    Ok(desc.frames)
+}
@@ -0,0 +1,312 @@
+use heron::rapier_plugin::PhysicsWorld;
+use rand::Rng;
+use serde::Deserialize;
+
+use crate::{
+    assets::{BuildingFrames, BulletFrames},
+    bullet::{spawn_bullet, BulletStats},
+    economy::{Bank, Purchase},
+    events::BulletFired,
+    player::PlayerInputLabel,
+    prelude::*,
+    unit::RangedStats,
+    world_ui::Percentage,
+};
+
+/// One upgrade level of a spawner, part of `GameTuning`
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct SpawnerTier {
+    /// What it costs to reach this tier from the one below
+    pub cost: u32,
+    /// How many times faster than normal the spawner makes minions
+    pub spawn_rate: f32,
+    /// Multiplies the health of the spawner's minions
+    pub minion_health: f32,
+    /// Radius of the area heroes and minions capture the spawner from
+    pub capture_radius: f32,
+    /// Lets the spawner shoot at enemies near it
+    #[serde(default)]
+    pub turret: Option<RangedStats>,
+}
+
+impl SpawnerTier {
+    /// What every spawner is before it's upgraded
+    pub const BASE: Self = Self {
+        cost: 0,
+        spawn_rate: 1.0,
+        minion_health: 1.0,
+        capture_radius: 0.2,
+        turret: None,
+    };
+}
+
+/// Every level a spawner can reach, the first is the one spawners start at
+#[derive(Debug, Clone, Deserialize)]
+#[serde(transparent)]
+pub struct SpawnerTiers(pub Vec<SpawnerTier>);
+
+impl Default for SpawnerTiers {
+    fn default() -> Self {
+        Self(vec![
+            SpawnerTier::BASE,
+            SpawnerTier {
+                cost: 40,
+                spawn_rate: 1.25,
+                capture_radius: 0.25,
+                ..SpawnerTier::BASE
+            },
+            SpawnerTier {
+                cost: 80,
+                spawn_rate: 1.25,
+                minion_health: 1.5,
+                capture_radius: 0.3,
+                turret: Some(RangedStats {
+                    damage: 0.5,
+                    range: 1.0,
+                    cooldown: 1.0,
+                    bullet_speed: 1.0,
+                }),
+            },
+            SpawnerTier {
+                cost: 160,
+                spawn_rate: 1.5,
+                minion_health: 1.5,
+                capture_radius: 0.35,
+                turret: Some(RangedStats {
+                    damage: 1.0,
+                    range: 1.3,
+                    cooldown: 0.6,
+                    bullet_speed: 1.2,
+                }),
+            },
+        ])
+    }
+}
+
+impl SpawnerTiers {
+    /// The tier at `level`, the highest one if `level` is past it
+    pub fn get(&self, level: usize) -> SpawnerTier {
+        self.0
+            .get(level)
+            .or_else(|| self.0.last())
+            .copied()
+            .unwrap_or(SpawnerTier::BASE)
+    }
+
+    pub fn max_level(&self) -> usize {
+        self.0.len().saturating_sub(1)
+    }
+
+    /// What going up from `level` costs, `None` at the top
+    pub fn next_cost(&self, level: usize) -> Option<u32> {
+        self.0.get(level + 1).map(|tier| tier.cost)
+    }
+}
+
+/// The bar under a spawner's capture bar that fills as it's upgraded
+#[derive(Component)]
+pub struct UpgradeBar;
+
+/// Puts a spawner on `level` and swaps in that tier's capture area and turret
+pub fn set_level(
+    commands: &mut Commands,
+    spawner_ent: Entity,
+    spawner: &mut Spawner,
+    level: usize,
+    tiers: &SpawnerTiers,
+) {
+    spawner.level = level;
+    let tier = tiers.get(level);
+    commands.entity(spawner_ent).insert(CollisionShape::Sphere {
+        radius: tier.capture_radius,
+    });
+    match tier.turret {
+        Some(turret) => {
+            commands.entity(spawner_ent).insert(RangedAttack {
+                damage: turret.damage,
+                range: turret.range,
+                bullet_speed: turret.bullet_speed,
+                cooldown: Timer::from_seconds(turret.cooldown, true),
+            });
+        }
+        None => {
+            commands.entity(spawner_ent).remove::<RangedAttack>();
+        }
+    }
+}
+
+pub struct UpgradePlugin;
+
+impl Plugin for UpgradePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(
+            SystemSet::on_update(GameState::GamePlay)
+                .with_system(buy_spawner_upgrades.after(PlayerInputLabel))
+                .with_system(spawner_turrets)
+                .with_system(show_spawner_level),
+        );
+    }
+}
+
+/// Players upgrade the owned spawner they are standing on,
+/// teams played by the AI upgrade their lowest spawner as soon as they can afford it
+pub fn buy_spawner_upgrades(
+    mut commands: Commands,
+    players: Query<(&PlayerIntent, &GlobalTransform, &Team, &RespawnTimer), With<Player>>,
+    mut spawners: Query<(Entity, &mut Spawner, &GlobalTransform, &Team)>,
+    mut bank: Bank,
+    difficulty: Res<Difficulty>,
+    tuning: Res<GameTuning>,
+) {
+    let tiers = &tuning.spawner_tiers;
+    let mut purchases = Vec::new();
+
+    for (intent, transform, team, respawn) in players.iter() {
+        if intent.purchase != Some(Purchase::SpawnerUpgrade) || respawn.is_dead {
+            continue;
+        }
+        let position = transform.translation.truncate();
+        let standing_on = spawners
+            .iter()
+            .filter(|(_, spawner, spawner_transform, owner)| {
+                *owner == team
+                    && spawner_transform.translation.truncate().distance(position)
+                        <= tiers.get(spawner.level).capture_radius
+            })
+            .min_by_key(|(entity, ..)| *entity);
+        if let Some((spawner_ent, ..)) = standing_on {
+            purchases.push((*team, spawner_ent));
+        }
+    }
+
+    let mut ai_teams: Vec<Team> = spawners
+        .iter()
+        .map(|(.., team)| *team)
+        .filter(|team| difficulty.scales(*team))
+        .collect();
+    ai_teams.sort();
+    ai_teams.dedup();
+    for team in ai_teams {
+        let lowest = spawners
+            .iter()
+            .filter(|(_, spawner, _, owner)| {
+                **owner == team && tiers.next_cost(spawner.level).is_some()
+            })
+            .min_by_key(|(entity, spawner, ..)| (spawner.level, *entity));
+        if let Some((spawner_ent, spawner, ..)) = lowest {
+            let cost = tiers.next_cost(spawner.level).unwrap_or_default();
+            if bank.get(team) >= cost {
+                purchases.push((team, spawner_ent));
+            }
+        }
+    }
+
+    for (team, spawner_ent) in purchases {
+        let (_, mut spawner, ..) = match spawners.get_mut(spawner_ent) {
+            Ok(spawner) => spawner,
+            Err(_) => continue,
+        };
+        let cost = match tiers.next_cost(spawner.level) {
+            Some(cost) => cost,
+            None => continue,
+        };
+        if bank.spend(team, cost, Purchase::SpawnerUpgrade) {
+            let level = spawner.level + 1;
+            set_level(&mut commands, spawner_ent, &mut spawner, level, tiers);
+        }
+    }
+}
+
+/// Upgraded spawners shoot the closest enemy in range, ties go to the lowest entity
+fn spawner_turrets(
+    mut commands: Commands,
+    mut turrets: Query<(&mut RangedAttack, &GlobalTransform, &Team), With<Spawner>>,
+    targets: Query<
+        (Entity, &GlobalTransform, &Team, Option<&RespawnTimer>),
+        Or<(With<Player>, With<Enemy>, With<Minion>)>,
+    >,
+    physics_world: PhysicsWorld,
+    time: Res<GameTime>,
+    mut rng: ResMut<GameRng>,
+    mut fired: EventWriter<BulletFired>,
+    bullets: Option<Res<BulletFrames>>,
+) {
+    let delta = time.delta();
+
+    for (mut attack, global_transform, team) in turrets.iter_mut() {
+        if !attack.cooldown.finished() {
+            attack.cooldown.tick(delta);
+            continue;
+        }
+
+        let position = global_transform.translation.truncate();
+        let target = targets
+            .iter()
+            .filter(|(_, _, target_team, respawn)| {
+                *target_team != team && !respawn.map(|respawn| respawn.is_dead).unwrap_or(false)
+            })
+            .map(|(entity, transform, ..)| (entity, transform.translation.truncate()))
+            .filter(|(_, target)| target.distance(position) <= attack.range)
+            .filter(|(_, target)| has_line_of_sight(&physics_world, position, *target))
+            .min_by(|(a, target_a), (b, target_b)| {
+                target_a
+                    .distance(position)
+                    .partial_cmp(&target_b.distance(position))
+                    .unwrap_or(std::cmp::Ordering::Equal)
+                    .then(a.cmp(b))
+            });
+
+        if let Some((_, target)) = target {
+            attack.cooldown.tick(delta);
+            let direction = (target - position).try_normalize().unwrap_or(Vec2::X);
+            let frame = rng.gen_range(0..2) + if *team == Team::PLAYER { 0 } else { 2 };
+            spawn_bullet(
+                &mut commands,
+                &mut fired,
+                Transform::from_translation(global_transform.translation + Vec3::Z),
+                *team,
+                BulletStats::from(&*attack),
+                direction,
+                bullets.as_deref().map(|bullets| (bullets, frame)),
+            );
+        }
+    }
+}
+
+/// Grows the building with each level and fills its upgrade bar
+fn show_spawner_level(
+    mut spawners: Query<(
+        &Spawner,
+        Option<&Team>,
+        Option<&Children>,
+        Option<&mut TextureAtlasSprite>,
+    )>,
+    mut bars: Query<&mut Percentage, With<UpgradeBar>>,
+    factions: Res<Factions>,
+    tuning: Res<GameTuning>,
+    buildings: Option<Res<BuildingFrames>>,
+) {
+    let max_level = tuning.spawner_tiers.max_level().max(1);
+
+    for (spawner, team, children, sprite) in spawners.iter_mut() {
+        let filled = spawner.level as f32 / max_level as f32;
+
+        for child in children.into_iter().flat_map(|children| children.iter()) {
+            if let Ok(mut bar) = bars.get_mut(*child) {
+                bar.value = filled;
+                bar.color = team
+                    .map(|team| factions.color(*team))
+                    .unwrap_or(Color::GRAY);
+            }
+        }
+
+        if let (Some(mut sprite), Some(buildings)) = (sprite, buildings.as_ref()) {
+            let base = buildings
+                .frames
+                .iter()
+                .find(|frame| frame.index == sprite.index)
+                .and_then(|frame| frame.custom_size);
+            sprite.custom_size = base.map(|size| size * (1.0 + 0.15 * spawner.level as f32));
+        }
+    }
+}
@@ -0,0 +1,196 @@
+use std::collections::BTreeMap;
+
+use rand::Rng;
+use serde::Deserialize;
+
+use crate::{
+    assets::BulletFrames,
+    bullet::{spawn_bullet, BulletStats, DEFAULT_LIFETIME, OVERSHOOT},
+    events::BulletFired,
+    prelude::*,
+};
+
+/// How a gun shoots, part of `GameTuning` and named by the `weapon` of a unit
+#[derive(Debug, Clone, Deserialize)]
+pub struct WeaponDef {
+    /// Seconds between shots
+    pub cooldown: f32,
+    /// Damage of each projectile
+    pub damage: f32,
+    pub range: f32,
+    pub projectile_speed: f32,
+    #[serde(default = "default_projectile_size")]
+    pub projectile_size: f32,
+    /// Projectiles fired by each shot
+    #[serde(default = "one")]
+    pub pellets: u32,
+    /// Degrees across the cone the pellets are spread over
+    #[serde(default)]
+    pub spread: f32,
+    /// How many units a projectile goes through before it stops
+    #[serde(default)]
+    pub pierce: u32,
+    /// Bullet frames a projectile picks from, empty uses its team's
+    #[serde(default)]
+    pub sprites: Vec<usize>,
+    /// Seconds a projectile lasts if it doesn't fly out of range first
+    #[serde(default = "default_lifetime")]
+    pub lifetime: f32,
+    #[serde(default)]
+    pub falloff: Option<Falloff>,
+    #[serde(default)]
+    pub walls: WallBehavior,
+}
+
+fn default_projectile_size() -> f32 {
+    0.1
+}
+
+fn default_lifetime() -> f32 {
+    DEFAULT_LIFETIME
+}
+
+fn one() -> u32 {
+    1
+}
+
+/// Every weapon by name
+#[derive(Debug, Clone, Deserialize)]
+#[serde(transparent)]
+pub struct Weapons(pub BTreeMap<String, WeaponDef>);
+
+impl Default for Weapons {
+    fn default() -> Self {
+        let egg_gun = WeaponDef {
+            cooldown: 0.35,
+            damage: 1.0,
+            range: 2.5,
+            projectile_speed: 1.0,
+            projectile_size: default_projectile_size(),
+            pellets: 1,
+            spread: 0.0,
+            pierce: 0,
+            sprites: vec![0, 1],
+            lifetime: default_lifetime(),
+            falloff: None,
+            walls: WallBehavior::Stop,
+        };
+        let bone_gun = WeaponDef {
+            cooldown: 0.6,
+            sprites: vec![2, 3],
+            ..egg_gun.clone()
+        };
+        let shotgun = WeaponDef {
+            cooldown: 0.9,
+            damage: 0.4,
+            range: 1.5,
+            projectile_speed: 1.2,
+            pellets: 5,
+            spread: 30.0,
+            sprites: Vec::new(),
+            falloff: Some(Falloff {
+                start: 0.5,
+                min: 0.25,
+            }),
+            ..egg_gun.clone()
+        };
+        let lance = WeaponDef {
+            cooldown: 1.0,
+            damage: 1.5,
+            range: 3.0,
+            projectile_speed: 1.6,
+            pierce: 2,
+            sprites: Vec::new(),
+            walls: WallBehavior::Ricochet { bounces: 2 },
+            ..egg_gun.clone()
+        };
+        Self(
+            [
+                ("egg_gun", egg_gun),
+                ("bone_gun", bone_gun),
+                ("shotgun", shotgun),
+                ("lance", lance),
+            ]
+            .into_iter()
+            .map(|(name, weapon)| (name.to_string(), weapon))
+            .collect(),
+        )
+    }
+}
+
+impl Weapons {
+    pub fn get(&self, name: &str) -> Option<&WeaponDef> {
+        self.0.get(name)
+    }
+}
+
+/// The gun a hero shoots with. Its `RangedAttack` holds the numbers that change during a match
+#[derive(Component, Debug, Clone)]
+pub struct Weapon(pub WeaponDef);
+
+/// Gives `entity` the weapon, replacing whatever it shot with before
+pub fn equip_weapon(commands: &mut Commands, entity: Entity, weapon: &WeaponDef) {
+    commands
+        .entity(entity)
+        .insert(Weapon(weapon.clone()))
+        .insert(RangedAttack {
+            damage: weapon.damage,
+            range: weapon.range,
+            bullet_speed: weapon.projectile_speed,
+            cooldown: Timer::from_seconds(weapon.cooldown, true),
+        });
+}
+
+/// Fires one shot of `weapon` along `aim` and returns the projectiles.
+/// Pellets are spaced evenly across the spread with a little jitter each, drawn from `rng`
+pub fn fire_weapon(
+    commands: &mut Commands,
+    fired: &mut EventWriter<BulletFired>,
+    rng: &mut GameRng,
+    transform: Transform,
+    team: Team,
+    weapon: &WeaponDef,
+    attack: &RangedAttack,
+    aim: Vec2,
+    bullets: Option<&BulletFrames>,
+) -> Vec<Entity> {
+    let stats = BulletStats {
+        damage: attack.damage,
+        speed: attack.bullet_speed,
+        size: weapon.projectile_size,
+        pierce: weapon.pierce,
+        range: attack.range * OVERSHOOT,
+        lifetime: weapon.lifetime,
+        falloff: weapon.falloff,
+        walls: weapon.walls,
+    };
+    let pellets = weapon.pellets.max(1);
+    let spread = weapon.spread.to_radians();
+    let step = spread / pellets as f32;
+
+    (0..pellets)
+        .map(|pellet| {
+            let angle = if spread > 0.0 {
+                -spread / 2.0 + step * (pellet as f32 + rng.gen_range(0.0..1.0))
+            } else {
+                0.0
+            };
+            let direction = Mat2::from_angle(angle) * aim;
+
+            // Player bullets are the first pair of frames, everyone else's the second
+            let frame = match weapon.sprites.len() {
+                0 => rng.gen_range(0..2) + if team == Team::PLAYER { 0 } else { 2 },
+                count => weapon.sprites[rng.gen_range(0..count)],
+            };
+            spawn_bullet(
+                commands,
+                fired,
+                transform,
+                team,
+                stats,
+                direction,
+                bullets.map(|bullets| (bullets, frame)),
+            )
+        })
+        .collect()
+}
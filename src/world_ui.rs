@@ -43,10 +43,12 @@ pub struct BarMaterialUniformData {
     color_2: Vec4,
 }
 
+/// How full the bar is, filled with `color`
 #[derive(Component, Clone, Copy, Inspectable)]
 pub struct Percentage {
-    #[inspectable(min = -1.0, max = 1.0)]
+    #[inspectable(min = 0.0, max = 1.0)]
     pub value: f32,
+    pub color: Color,
 }
 
 pub fn spawn_quad(
@@ -58,9 +60,9 @@ pub fn spawn_quad(
         .spawn_bundle(MaterialMesh2dBundle {
             mesh: mesh_assets.add(Mesh::from(shape::Quad::default())).into(),
             material: my_material_assets.add(BarMaterial {
-                percentage: 0.5,
-                color_1: Color::RED,
-                color_2: Color::GREEN,
+                percentage: 0.0,
+                color_1: Color::GRAY,
+                color_2: Color::GRAY,
             }),
             transform: Transform {
                 translation: bevy::prelude::Vec3::new(0.0, 0.15, 0.0),
@@ -69,7 +71,10 @@ pub fn spawn_quad(
             },
             ..default()
         })
-        .insert(Percentage { value: 0.0 })
+        .insert(Percentage {
+            value: 0.0,
+            color: Color::GRAY,
+        })
         .id()
 }
 
@@ -104,6 +109,7 @@ fn prepare_my_material(
     for (percent, handle) in percent_query.iter() {
         if let Some(material) = material_assets.get_mut(handle) {
             material.uniform_data.percentage = percent.value;
+            material.uniform_data.color_2 = percent.color.as_linear_rgba_f32().into();
         }
     }
 
@@ -0,0 +1,42 @@
+use std::fs;
+
+use bevy::utils::Duration;
+use rust_gamejam::{
+    bot::take_over_player,
+    headless::{build_headless_app, run_match, MAX_STEPS},
+    map::Map,
+    prelude::GameRng,
+};
+
+fn main_map() -> Map {
+    Map::parse(&fs::read("assets/main.map").unwrap(), "main").unwrap()
+}
+
+#[test]
+fn bot_match_on_main_map_ends() {
+    let mut app = build_headless_app(main_map(), Duration::from_secs_f32(1.0 / 60.0)).unwrap();
+    app.add_system(take_over_player);
+
+    let result = run_match(&mut app, MAX_STEPS);
+    assert!(
+        result.is_some(),
+        "match did not finish in {} steps",
+        MAX_STEPS
+    );
+}
+
+#[test]
+fn identical_matches_end_the_same() {
+    let results: Vec<_> = (0..2)
+        .map(|_| {
+            let mut app =
+                build_headless_app(main_map(), Duration::from_secs_f32(1.0 / 60.0)).unwrap();
+            app.insert_resource(GameRng::from_seed(7))
+                .add_system(take_over_player);
+            run_match(&mut app, MAX_STEPS)
+        })
+        .collect();
+
+    assert!(results[0].is_some());
+    assert_eq!(results[0], results[1]);
+}